@@ -1,9 +1,23 @@
-use std::fs;
+use std::{fs, hint::black_box};
 
-use criterion::{Criterion, Throughput, criterion_group, criterion_main};
-use pct_enc::{naive::*, *};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use pct_enc::{
+    naive::{table_bitset::PATH, *},
+    *,
+};
 
-criterion_group!(benches, bench_validate);
+criterion_group!(
+    benches,
+    bench_validate,
+    bench_validate_short,
+    bench_validate_many,
+    bench_validate_iri,
+    bench_decode,
+    bench_decode_utf8,
+    bench_encode,
+    bench_normalize,
+    bench_split_query
+);
 criterion_main!(benches);
 
 fn bench_validate(c: &mut Criterion) {
@@ -20,36 +34,278 @@ fn bench_validate(c: &mut Criterion) {
     });
 
     group.bench_function("ssse3_3load", |b| {
-        b.iter(|| unsafe { ssse3::validate_3load(&src) })
+        b.iter(|| unsafe { ssse3::validate_3load(PATH, &src) })
     });
 
     group.bench_function("sse41_3load", |b| {
-        b.iter(|| unsafe { sse41::validate_3load(&src) })
+        b.iter(|| unsafe { sse41::validate_3load(PATH, &src) })
     });
     group.bench_function("sse41_alignr", |b| {
-        b.iter(|| unsafe { sse41::validate_alignr(&src) })
+        b.iter(|| unsafe { sse41::validate_alignr(PATH, &src) })
     });
     group.bench_function("sse41_alignl", |b| {
-        b.iter(|| unsafe { sse41::validate_alignl(&src) })
+        b.iter(|| unsafe { sse41::validate_alignl(PATH, &src) })
     });
 
     group.bench_function("avx2_3load", |b| {
-        b.iter(|| unsafe { avx2::validate_3load(&src) })
+        b.iter(|| unsafe { avx2::validate_3load(PATH, &src) })
     });
     group.bench_function("avx2_alignr", |b| {
-        b.iter(|| unsafe { avx2::validate_alignr(&src) })
+        b.iter(|| unsafe { avx2::validate_alignr(PATH, &src) })
     });
     group.bench_function("avx2_alignl", |b| {
-        b.iter(|| unsafe { avx2::validate_alignl(&src) })
+        b.iter(|| unsafe { avx2::validate_alignl(PATH, &src) })
     });
 
     group.bench_function("avx512_3load", |b| {
-        b.iter(|| unsafe { avx512::validate_3load(&src) })
+        b.iter(|| unsafe { avx512::validate_3load(PATH, &src) })
     });
     group.bench_function("avx512_3load_gf2p8affine", |b| {
-        b.iter(|| unsafe { avx512::validate_3load_gf2p8affine(&src) })
+        b.iter(|| unsafe { avx512::validate_3load_gf2p8affine(PATH, &src) })
     });
     group.bench_function("avx512_3load_perm", |b| {
-        b.iter(|| unsafe { avx512::validate_3load_perm(&src) })
+        b.iter(|| unsafe { avx512::validate_3load_perm(PATH, &src) })
     });
+    group.bench_function("avx512_alignr", |b| {
+        b.iter(|| unsafe { avx512::validate_alignr(PATH, &src) })
+    });
+    group.bench_function("avx512_alignl", |b| {
+        b.iter(|| unsafe { avx512::validate_alignl(PATH, &src) })
+    });
+    group.bench_function("avx512_iri", |b| {
+        b.iter(|| unsafe { avx512::validate_iri(PATH, &src) })
+    });
+
+    for threads in [2, 4] {
+        group.bench_function(format!("par_{threads}"), |b| {
+            b.iter(|| par_validate(PATH, &src, threads))
+        });
+    }
+}
+
+fn bench_validate_short(c: &mut Criterion) {
+    type ValidateFn = unsafe fn(table_bitset::Table, &[u8]) -> bool;
+
+    let fns: [(&str, ValidateFn); 4] = [
+        ("naive_bitset", |table, src| table.validate(src)),
+        ("avx512_3load", avx512::validate_3load),
+        (
+            "avx512_3load_gf2p8affine",
+            avx512::validate_3load_gf2p8affine,
+        ),
+        ("avx512_3load_perm", avx512::validate_3load_perm),
+    ];
+
+    // the sizes of typical URL paths
+    let src = b"/static/img/%E2%82%AC/logo-v2.png".repeat(4);
+
+    let mut group = c.benchmark_group("validate_short");
+    for len in [8, 32, 64, 100] {
+        let src = &src[..len];
+        assert!(PATH.validate(src));
+        group.throughput(Throughput::Bytes(len as u64));
+
+        for (name, f) in fns {
+            group.bench_with_input(BenchmarkId::new(name, len), src, |b, src| {
+                b.iter(|| unsafe { f(PATH, black_box(src)) })
+            });
+        }
+    }
+}
+
+fn bench_validate_many(c: &mut Criterion) {
+    // path segments of 5 to 40 bytes
+    let segments = [
+        "users",
+        "%E2%82%AC",
+        "profile-picture",
+        "2024",
+        "index.html",
+    ];
+    let srcs: Vec<Vec<u8>> = (0..10000)
+        .map(|i| segments[i % 5].repeat(i % 3 + 1).into_bytes())
+        .collect();
+    let srcs: Vec<&[u8]> = srcs.iter().map(|src| &src[..]).collect();
+    let len: usize = srcs.iter().map(|src| src.len()).sum();
+
+    let mut group = c.benchmark_group("validate_many");
+    group.throughput(Throughput::Bytes(len as u64));
+
+    group.bench_function("naive_bitset", |b| {
+        b.iter(|| {
+            srcs.iter()
+                .map(|src| PATH.validate(src))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("dispatch_each", |b| {
+        b.iter(|| {
+            srcs.iter()
+                .map(|src| validate(PATH, src))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("avx512", |b| {
+        b.iter(|| unsafe { avx512::validate_many(PATH, &srcs) })
+    });
+}
+
+fn bench_validate_iri(c: &mut Criterion) {
+    use table_bitset::IPATH;
+
+    let src = "/wiki/Straße/日本語/😀/%E2%82%AC/Ωμέγα".repeat(2000);
+    let src = src.as_bytes();
+
+    let mut group = c.benchmark_group("validate_iri");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+
+    group.bench_function("naive_bitset", |b| b.iter(|| IPATH.validate(src)));
+    group.bench_function("naive_bool_array", |b| {
+        b.iter(|| table_bool_array::IPATH.validate(src))
+    });
+    group.bench_function("avx512_3load_perm", |b| {
+        b.iter(|| unsafe { avx512::validate_3load_perm(IPATH, src) })
+    });
+    group.bench_function("avx512_iri", |b| {
+        b.iter(|| unsafe { avx512::validate_iri(IPATH, src) })
+    });
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let src = fs::read("enc.txt").unwrap();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+
+    group.bench_function("naive", |b| b.iter(|| Decode::new(&src).to_bytes()));
+    group.bench_function("naive_checked", |b| {
+        b.iter(|| naive::decode_checked(PATH, &src))
+    });
+    group.bench_function("avx512_checked", |b| {
+        b.iter(|| unsafe { avx512::decode_checked(PATH, &src) })
+    });
+
+    let mut bench_simd = |name, f: unsafe fn(&[u8], *mut u8) -> usize| {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut dst = Vec::with_capacity(src.len());
+                unsafe {
+                    let len = f(&src, dst.as_mut_ptr());
+                    dst.set_len(len);
+                }
+                dst
+            })
+        });
+    };
+
+    bench_simd("avx2", avx2::decode);
+    bench_simd("avx512", avx512::decode);
+
+    // the copy is included, as the buffer is overwritten
+    let mut bench_in_place = |name, f: unsafe fn(&mut [u8]) -> usize| {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut buf = src.clone();
+                let len = unsafe { f(&mut buf) };
+                buf.truncate(len);
+                buf
+            })
+        });
+    };
+
+    bench_in_place("naive_in_place", naive::decode_in_place);
+    bench_in_place("avx512_in_place", avx512::decode_in_place);
+}
+
+fn bench_decode_utf8(c: &mut Criterion) {
+    let src = "/wiki/Straße/日本語/😀/%E2%82%AC/Ωμέγα".repeat(2000);
+    let src = iri_to_uri(src.as_bytes());
+
+    let mut group = c.benchmark_group("decode_utf8");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+
+    group.bench_function("naive", |b| {
+        b.iter(|| String::from_utf8(Decode::new(&src).to_bytes().into_owned()))
+    });
+    group.bench_function("to_str", |b| b.iter(|| Decode::new(&src).to_str()));
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let src = fs::read("raw.bin").unwrap();
+
+    let mut group = c.benchmark_group("encode");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+
+    group.bench_function("naive", |b| {
+        b.iter(|| {
+            let mut dst = Vec::new();
+            for chunk in Encode::new(PATH, &src) {
+                dst.extend_from_slice(chunk.as_bytes());
+            }
+            dst
+        })
+    });
+
+    group.bench_function("avx2", |b| {
+        b.iter(|| {
+            let mut dst = Vec::new();
+            unsafe { avx2::encode_into(PATH, &src, &mut dst) };
+            dst
+        })
+    });
+    group.bench_function("avx512", |b| {
+        b.iter(|| {
+            let mut dst = Vec::new();
+            unsafe { avx512::encode_into(PATH, &src, &mut dst) };
+            dst
+        })
+    });
+    group.bench_function("avx512_lower", |b| {
+        b.iter(|| {
+            let mut dst = Vec::new();
+            unsafe { avx512::encode_into_with_case(PATH, &src, &mut dst, HexCase::Lower) };
+            dst
+        })
+    });
+}
+
+fn bench_normalize(c: &mut Criterion) {
+    let src = fs::read("enc.txt").unwrap();
+    // lowercase every hexadecimal digit so that every triplet changes
+    let src_lower = src.to_ascii_lowercase();
+
+    let mut group = c.benchmark_group("normalize");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+
+    for (name, src) in [("unchanged", &src), ("lower", &src_lower)] {
+        group.bench_function(format!("naive_{name}"), |b| {
+            b.iter(|| naive::normalize(PATH, src))
+        });
+        group.bench_function(format!("avx512_{name}"), |b| {
+            b.iter(|| unsafe { avx512::normalize(PATH, src) })
+        });
+    }
+}
+
+fn bench_split_query(c: &mut Criterion) {
+    use dispatch::Backend;
+
+    let src = "utm_source=newsletter&q=caf%C3%A9+au+lait;page=2&sort=desc&flag&".repeat(2000);
+    let src = src.as_bytes();
+
+    let mut group = c.benchmark_group("split_query");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+
+    for backend in [Backend::Naive, Backend::Avx2, Backend::Avx512] {
+        group.bench_function(backend.name(), |b| {
+            b.iter(|| query::split_query(src).with_backend(backend).count())
+        });
+        group.bench_function(format!("{backend}_checked"), |b| {
+            b.iter(|| {
+                query::split_query_checked(table_bitset::QUERY, src)
+                    .with_backend(backend)
+                    .count()
+            })
+        });
+    }
 }
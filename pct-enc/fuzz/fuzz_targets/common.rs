@@ -0,0 +1,62 @@
+//! Input splitting shared by the fuzz targets.
+
+use pct_enc::naive::table_bitset::{self, Table};
+
+const TABLES: [Table; 12] = [
+    table_bitset::PATH,
+    table_bitset::PCHAR,
+    table_bitset::QUERY,
+    table_bitset::UNRESERVED,
+    table_bitset::RESERVED,
+    table_bitset::REG_NAME,
+    table_bitset::ZONE_ID,
+    table_bitset::FORM_URLENCODED,
+    table_bitset::IPATH,
+    table_bitset::IQUERY,
+    table_bitset::UCSCHAR,
+    table_bitset::DIGIT.or_pct_encoded(),
+];
+
+/// Splits the fuzzer input into a table and a string.
+///
+/// The first byte selects either a predefined table, or an arbitrary one
+/// given by the next 16 bytes as a bitset over ASCII.
+pub fn split_input(data: &[u8]) -> Option<(Table, &[u8])> {
+    let (&flags, data) = data.split_first()?;
+    if flags & 0x80 == 0 {
+        return Some((TABLES[flags as usize % TABLES.len()], data));
+    }
+
+    let (bits, data) = data.split_first_chunk::<16>()?;
+    let bits = u128::from_le_bytes(*bits);
+    let bytes: Vec<u8> = (0..128)
+        .filter(|&x| x != b'%' && bits >> x & 1 != 0)
+        .collect();
+
+    let mut table = Table::new(&bytes);
+    if flags & 1 != 0 {
+        table = table.or_pct_encoded();
+    }
+    if flags & 2 != 0 {
+        table = table.or_ucschar();
+    }
+    if flags & 4 != 0 {
+        table = table.or_iprivate();
+    }
+    Some((table, data))
+}
+
+/// Checks whether the current CPU supports the feature.
+pub fn has_feature(feature: &str) -> bool {
+    match feature {
+        "ssse3" => is_x86_feature_detected!("ssse3"),
+        "sse4.1" => is_x86_feature_detected!("sse4.1"),
+        "avx2" => is_x86_feature_detected!("avx2"),
+        "avx512bw" => is_x86_feature_detected!("avx512bw"),
+        "avx512vbmi" => is_x86_feature_detected!("avx512vbmi"),
+        "avx512vbmi2" => is_x86_feature_detected!("avx512vbmi2"),
+        "gfni" => is_x86_feature_detected!("gfni"),
+        "bmi2" => is_x86_feature_detected!("bmi2"),
+        _ => panic!("unknown feature: {feature}"),
+    }
+}
@@ -0,0 +1,88 @@
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use pct_enc::{
+    avx2, avx512, decode_in_place,
+    naive::{self, Decode},
+    normalize,
+    stream::StreamDecoder,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((table, src)) = common::split_input(data) else {
+        return;
+    };
+    let vbmi2 = ["avx512bw", "avx512vbmi", "avx512vbmi2"]
+        .into_iter()
+        .all(common::has_feature);
+
+    let expected = naive::decode_checked(table, src).map(|s| s.into_owned());
+    assert_eq!(
+        pct_enc::decode_checked(table, src).as_deref(),
+        expected.as_deref()
+    );
+    if vbmi2 {
+        let res = unsafe { avx512::decode_checked(table, src) };
+        assert_eq!(res.as_deref(), expected.as_deref());
+    }
+
+    let mid = src.len() / 3;
+    let mut decoder = StreamDecoder::new(table);
+    let mut dst = Vec::new();
+    let res = decoder
+        .decode(&src[..mid], &mut dst)
+        .and_then(|()| decoder.decode(&src[mid..], &mut dst))
+        .and_then(|()| decoder.finish());
+    assert_eq!(res.map(|()| dst), expected);
+
+    let mut buf = src.to_vec();
+    let len = naive::decode_in_place(&mut buf);
+    let lenient = buf[..len].to_vec();
+    let mut buf = src.to_vec();
+    let len = decode_in_place(&mut buf);
+    assert_eq!(buf[..len], lenient);
+
+    assert_eq!(normalize(table, src), naive::normalize(table, src));
+
+    // the UTF-8 decoder rejects what it cannot decode
+    if vbmi2 {
+        let pct_encoded = src.iter().enumerate().all(|(i, &x)| {
+            x != b'%'
+                || matches!(src[i + 1..], [hi, lo, ..]
+                    if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit())
+        });
+        let mut dst = Vec::with_capacity(src.len());
+        let res = unsafe { avx512::decode_utf8(src, dst.as_mut_ptr()) };
+        assert_eq!(res.is_some(), pct_encoded);
+    }
+
+    // the rest requires properly percent-encoded input
+    let Ok(expected) = expected else {
+        return;
+    };
+    assert_eq!(lenient, expected);
+
+    let decode_with = |f: unsafe fn(&[u8], *mut u8) -> usize| {
+        let mut dst = Vec::with_capacity(src.len());
+        unsafe {
+            let len = f(src, dst.as_mut_ptr());
+            dst.set_len(len);
+        }
+        dst
+    };
+    if common::has_feature("avx2") {
+        assert_eq!(decode_with(avx2::decode), expected, "avx2");
+    }
+    if vbmi2 {
+        assert_eq!(decode_with(avx512::decode), expected, "avx512");
+    }
+
+    let s = Decode::new(src).to_str();
+    assert_eq!(s.as_deref().map_err(|e| *e), str::from_utf8(&expected));
+    assert_eq!(
+        Decode::new(src).to_string_lossy(),
+        String::from_utf8_lossy(&expected)
+    );
+});
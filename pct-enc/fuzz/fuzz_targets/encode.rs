@@ -0,0 +1,78 @@
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use pct_enc::{
+    avx2, avx512,
+    naive::{Decode, Encode, FormDecode, FormEncode, HexCase, iri_to_uri, uri_to_iri},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((table, src)) = common::split_input(data) else {
+        return;
+    };
+
+    let mut expected = Vec::new();
+    for chunk in Encode::new(table, src) {
+        expected.extend_from_slice(chunk.as_bytes());
+    }
+    // the encoded string is valid and decodes back to the original
+    assert!(table.or_pct_encoded().validate(&expected));
+    assert_eq!(Decode::new(&expected).to_bytes(), src);
+
+    let encode_preserving = |src: &[u8]| {
+        let mut dst = Vec::new();
+        for chunk in Encode::new(table, src).preserve_pct_encoded() {
+            dst.extend_from_slice(chunk.as_bytes());
+        }
+        dst
+    };
+    // preserving triplets also gives a valid string, which it leaves as is
+    let preserved = encode_preserving(src);
+    assert!(table.or_pct_encoded().validate(&preserved));
+    assert_eq!(encode_preserving(&preserved), preserved);
+    assert_eq!(encode_preserving(&expected), expected);
+
+    // only the case of the hexadecimal digits differs
+    let mut expected_lower = Vec::new();
+    for chunk in Encode::new(table, src).hex_case(HexCase::Lower) {
+        expected_lower.extend_from_slice(chunk.as_bytes());
+    }
+    assert!(expected_lower.eq_ignore_ascii_case(&expected));
+    assert_eq!(Decode::new(&expected_lower).to_bytes(), src);
+
+    if common::has_feature("avx2") {
+        let mut dst = Vec::new();
+        unsafe { avx2::encode_into(table, src, &mut dst) };
+        assert_eq!(dst, expected, "avx2");
+        dst.clear();
+        unsafe { avx2::encode_into_with_case(table, src, &mut dst, HexCase::Lower) };
+        assert_eq!(dst, expected_lower, "avx2 lower");
+    }
+    if ["avx512bw", "avx512vbmi", "avx512vbmi2", "bmi2"]
+        .into_iter()
+        .all(common::has_feature)
+    {
+        let mut dst = b"prefix".to_vec();
+        unsafe { avx512::encode_into(table, src, &mut dst) };
+        assert_eq!(dst[6..], expected, "avx512");
+        dst.truncate(6);
+        unsafe { avx512::encode_into_with_case(table, src, &mut dst, HexCase::Lower) };
+        assert_eq!(dst[6..], expected_lower, "avx512 lower");
+    }
+
+    assert_eq!(
+        FormDecode::new(&FormEncode::new(src).to_bytes()).to_bytes(),
+        src
+    );
+
+    // IRI conversion round-trips on well-formed UTF-8 with no `%`,
+    // up to the characters left encoded
+    if str::from_utf8(src).is_ok() && !src.contains(&b'%') {
+        let uri = iri_to_uri(src);
+        assert!(uri.is_ascii());
+        assert_eq!(Decode::new(&uri).to_bytes(), src);
+        assert_eq!(Decode::new(&uri_to_iri(&uri)).to_bytes(), src);
+    }
+});
@@ -0,0 +1,100 @@
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use pct_enc::{
+    ValidateError, avx2, avx512,
+    dispatch::Backend,
+    naive::{Table as _, table_bitset::Table, table_bool_array},
+    query, sse41, ssse3,
+    stream::StreamValidator,
+};
+
+type DetailedFn = unsafe fn(Table, &[u8]) -> Result<(), ValidateError>;
+
+const DETAILED_FNS: [(&str, &[&str], DetailedFn); 13] = [
+    ("ssse3_3load", &["ssse3"], ssse3::validate_3load_detailed),
+    ("sse41_3load", &["sse4.1"], sse41::validate_3load_detailed),
+    ("sse41_alignr", &["sse4.1"], sse41::validate_alignr_detailed),
+    ("sse41_alignl", &["sse4.1"], sse41::validate_alignl_detailed),
+    ("avx2_3load", &["avx2"], avx2::validate_3load_detailed),
+    ("avx2_alignr", &["avx2"], avx2::validate_alignr_detailed),
+    ("avx2_alignl", &["avx2"], avx2::validate_alignl_detailed),
+    (
+        "avx512_3load",
+        &["avx512bw"],
+        avx512::validate_3load_detailed,
+    ),
+    (
+        "avx512_3load_gf2p8affine",
+        &["avx512bw", "gfni"],
+        avx512::validate_3load_gf2p8affine_detailed,
+    ),
+    (
+        "avx512_3load_perm",
+        &["avx512bw", "avx512vbmi"],
+        avx512::validate_3load_perm_detailed,
+    ),
+    (
+        "avx512_alignr",
+        &["avx512bw"],
+        avx512::validate_alignr_detailed,
+    ),
+    (
+        "avx512_alignl",
+        &["avx512bw"],
+        avx512::validate_alignl_detailed,
+    ),
+    (
+        "avx512_iri",
+        &["avx512bw", "avx512vbmi"],
+        avx512::validate_iri_detailed,
+    ),
+];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((table, src)) = common::split_input(data) else {
+        return;
+    };
+    let expected = table.validate_detailed(src);
+    assert_eq!(table.validate(src), expected.is_ok());
+
+    let bool_array = table_bool_array::Table::from_bitset(table);
+    assert_eq!(bool_array.validate_detailed(src), expected);
+
+    for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+        assert_eq!(backend.validate(table, src), expected.is_ok(), "{backend}");
+        assert_eq!(backend.validate_detailed(table, src), expected, "{backend}");
+    }
+
+    for (name, features, f) in DETAILED_FNS {
+        if features.iter().all(|&x| common::has_feature(x)) {
+            assert_eq!(unsafe { f(table, src) }, expected, "{name}");
+        }
+    }
+
+    // split the string in two at a point derived from its length
+    let mid = src.len() * 7 / 13;
+    let mut validator = StreamValidator::new(table);
+    let res = validator
+        .update(&src[..mid])
+        .and_then(|()| validator.update(&src[mid..]))
+        .and_then(|()| validator.finish());
+    assert_eq!(res, expected);
+
+    // the segments of a path, packed together
+    let srcs: Vec<&[u8]> = src.split(|&x| x == b'/').collect();
+    let res = pct_enc::validate_many(table, &srcs);
+    assert_eq!(
+        res,
+        srcs.iter().map(|s| table.validate(s)).collect::<Vec<_>>()
+    );
+
+    let res: Result<Vec<_>, _> = query::split_query_checked(table, src).collect();
+    let expected = table
+        .or(query::DELIMITERS)
+        .validate_detailed(src)
+        .map(|()| query::split_query(src).collect::<Vec<_>>());
+    assert_eq!(res, expected);
+});
@@ -1,24 +1,36 @@
-use crate::naive::table_bitset;
+use crate::{
+    ValidateError,
+    naive::{self, HexCase, table_bitset::Table},
+};
 use std::arch::x86_64::*;
 
+#[target_feature(enable = "avx2")]
+pub unsafe fn validate_3load(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_3load_detailed(table, src).is_ok()
+}
+
 #[rustc_align(64)]
 #[target_feature(enable = "avx2")]
-pub unsafe fn validate_3load(src: &[u8]) -> bool {
+pub unsafe fn validate_3load_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
     let mut i = 0;
     if len >= 32 + 2 {
-        if !super::validate_first_two(src) {
-            return false;
+        if !super::first_two_valid(table, src) {
+            return super::fail_in_first_two(table, src);
         }
 
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
+        // the corresponding bit for % is set in this table iff it is allowed
+        let allowed = table.bits();
         let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
         let allowed = _mm256_broadcastsi128_si256(allowed);
 
-        let hexdig = table_bitset::HEXDIG.bits();
+        let hexdig = super::hexdig_after_pct(table).bits();
         let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
         let hexdig = _mm256_broadcastsi128_si256(hexdig);
 
@@ -58,28 +70,40 @@ pub unsafe fn validate_3load(src: &[u8]) -> bool {
             let is_invalid = _mm256_movemask_epi8(is_invalid); // <=4 1 1*p0
 
             if is_invalid != 0 {
-                return false;
+                let j = i + 2 + is_invalid.trailing_zeros() as usize;
+                return super::fail_at(table, src, j);
             }
             i += 32;
         }
+        // the loop has validated `src[..i + 2]`
+        i += 2;
     }
-    table_bitset::PATH.validate(&src[i..])
+    super::validate_rest(table, src, i)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn validate_alignr(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_alignr_detailed(table, src).is_ok()
 }
 
 #[rustc_align(64)]
 #[target_feature(enable = "avx2")]
-pub unsafe fn validate_alignr(src: &[u8]) -> bool {
+pub unsafe fn validate_alignr_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
     let mut i = 0;
     if len >= 64 {
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
+        // the corresponding bit for % is set in this table iff it is allowed
+        let allowed = table.bits();
         let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
         let allowed = _mm256_broadcastsi128_si256(allowed);
 
-        let hexdig = table_bitset::HEXDIG.bits();
+        let hexdig = super::hexdig_after_pct(table).bits();
         let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
         let hexdig = _mm256_broadcastsi128_si256(hexdig);
 
@@ -123,7 +147,15 @@ pub unsafe fn validate_alignr(src: &[u8]) -> bool {
             let is_invalid = _mm256_movemask_epi8(is_invalid); // <=4 1 1*p0
 
             if is_invalid != 0 {
-                return false;
+                // bytes following a % are checked to be hexdig above
+                // but not necessarily allowed by the table, so exclude them
+                let is_pct = _mm256_movemask_epi8(is_pct) as u32 as u64;
+                let after_pct = super::after_pct_mask(src, i, is_pct);
+                let is_invalid = is_invalid as u32 as u64 & !after_pct;
+                if is_invalid != 0 {
+                    let j = i + is_invalid.trailing_zeros() as usize;
+                    return super::fail_at(table, src, j);
+                }
             }
 
             chunk = next_chunk;
@@ -131,21 +163,30 @@ pub unsafe fn validate_alignr(src: &[u8]) -> bool {
             i += 32;
         }
     }
-    table_bitset::PATH.validate(&src[i..])
+    super::validate_rest(table, src, i)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn validate_alignl(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_alignl_detailed(table, src).is_ok()
 }
 
 #[rustc_align(64)]
 #[target_feature(enable = "avx2")]
-pub unsafe fn validate_alignl(src: &[u8]) -> bool {
+pub unsafe fn validate_alignl_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
-    // the corresponding bit for % is set in this table
-    let allowed = table_bitset::PATH.bits();
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
     let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
     let allowed = _mm256_broadcastsi128_si256(allowed);
 
-    let hexdig = table_bitset::HEXDIG.bits();
+    let hexdig = super::hexdig_after_pct(table).bits();
     let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
     let hexdig = _mm256_broadcastsi128_si256(hexdig);
 
@@ -186,10 +227,316 @@ pub unsafe fn validate_alignl(src: &[u8]) -> bool {
         let is_invalid = _mm256_movemask_epi8(is_invalid); // <=4 1 1*p0
 
         if is_invalid != 0 {
-            return false;
+            let j = i + is_invalid.trailing_zeros() as usize;
+            return super::fail_at(table, src, j);
+        }
+        i += 32;
+    }
+    super::validate_rest(table, src, i)
+}
+
+/// Shuffle indices that move the bytes selected by an 8-bit mask to the front.
+const COMPRESS_TABLE: &[u64; 256] = &{
+    let mut table = [0; 256];
+    let mut mask = 0;
+    while mask < 256 {
+        let (mut idx, mut len) = (0u64, 0);
+        let mut i = 0;
+        while i < 8 {
+            if mask & (1 << i) != 0 {
+                idx |= (i as u64) << (len * 8);
+                len += 1;
+            }
+            i += 1;
         }
+        table[mask] = idx;
+        mask += 1;
+    }
+    table
+};
+
+/// Decodes a percent-encoded string into `dst`, returning the number of bytes written.
+///
+/// # Safety
+///
+/// `src` must be properly percent-encoded, that is, every `%` in it must be followed
+/// by two hexadecimal digits. `dst` must be valid for writes of `src.len()` bytes.
+#[rustc_align(64)]
+#[target_feature(enable = "avx2,popcnt")]
+pub unsafe fn decode(src: &[u8], dst: *mut u8) -> usize {
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // a hexdig is decoded as its low 4 bits plus 9 if it is a letter,
+    // where the addend is looked up by its high 4 bits
+    let letter_addend = _mm256_setr_epi8(
+        0, 0, 0, 0, 9, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+        0, 0, 0, 0, 9, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    );
+    let letter_addend_hi = _mm256_slli_epi16::<4>(letter_addend);
+
+    let pct = _mm256_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm256_set1_epi8(0xf);
+    let second_half = _mm_set_epi64x(0x0808080808080808, 0);
+
+    // bits set for the first two bytes of a chunk that follow a % in the previous chunk
+    let mut after_pct_prev = 0u32;
+
+    let mut i = 0;
+    let mut j = 0;
+    while i + 32 + 2 <= len {
+        let chunk = _mm256_loadu_si256(ptr.add(i).cast());
+        let chunk_r1 = _mm256_loadu_si256(ptr.add(i + 1).cast());
+        let chunk_r2 = _mm256_loadu_si256(ptr.add(i + 2).cast());
+
+        let is_pct = _mm256_cmpeq_epi8(chunk, pct);
+
+        // the low 4 bits are less than 16, so shifting words doesn't overflow
+        let hi_lo_4 = _mm256_and_si256(chunk_r1, byte_lo_4_mask);
+        let hi_lo_4 = _mm256_slli_epi16::<4>(hi_lo_4);
+        let hi_hi_4 = _mm256_and_si256(_mm256_srli_epi16::<4>(chunk_r1), byte_lo_4_mask);
+        let octet_hi = _mm256_add_epi8(hi_lo_4, _mm256_shuffle_epi8(letter_addend_hi, hi_hi_4));
+
+        let lo_lo_4 = _mm256_and_si256(chunk_r2, byte_lo_4_mask);
+        let lo_hi_4 = _mm256_and_si256(_mm256_srli_epi16::<4>(chunk_r2), byte_lo_4_mask);
+        let octet_lo = _mm256_add_epi8(lo_lo_4, _mm256_shuffle_epi8(letter_addend, lo_hi_4));
+
+        let octet = _mm256_or_si256(octet_hi, octet_lo);
+
+        // replace each % with the octet it starts
+        let decoded = _mm256_blendv_epi8(chunk, octet, is_pct);
+
+        // and drop the two hexdig following it
+        let is_pct = _mm256_movemask_epi8(is_pct) as u32;
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev;
+        after_pct_prev = (is_pct >> 30) | (is_pct >> 31);
+        let keep = !after_pct;
+
+        // compress each 8 bytes with a shuffle and store them one after another
+        for (k, half) in [
+            _mm256_castsi256_si128(decoded),
+            _mm256_extracti128_si256::<1>(decoded),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let keep = (keep >> (k * 16)) as usize;
+            let (keep_lo, keep_hi) = (keep & 0xff, (keep >> 8) & 0xff);
+
+            let idx = _mm_set_epi64x(COMPRESS_TABLE[keep_hi] as _, COMPRESS_TABLE[keep_lo] as _);
+            let idx = _mm_add_epi8(idx, second_half);
+            let compressed = _mm_shuffle_epi8(half, idx);
+
+            _mm_storel_epi64(dst.add(j).cast(), compressed);
+            j += keep_lo.count_ones() as usize;
+            _mm_storel_epi64(
+                dst.add(j).cast(),
+                _mm_unpackhi_epi64(compressed, compressed),
+            );
+            j += keep_hi.count_ones() as usize;
+        }
+        i += 32;
+    }
+    i += after_pct_prev.count_ones() as usize;
+    j + super::decode_rest(src, i, dst.add(j))
+}
+
+/// Shuffle indices that encode 4 bytes given a 4-bit mask of the bytes to encode,
+/// from a vector of the bytes, their high hexdig, their low hexdig and `%`, 4 each.
+const ENCODE_TABLE: &[[u8; 16]; 16] = &{
+    let mut table = [[0; 16]; 16];
+    let mut mask = 0;
+    while mask < 16 {
+        let mut len = 0;
+        let mut i = 0;
+        while i < 4 {
+            if mask & (1 << i) != 0 {
+                table[mask][len] = 12;
+                table[mask][len + 1] = 4 + i as u8;
+                table[mask][len + 2] = 8 + i as u8;
+                len += 3;
+            } else {
+                table[mask][len] = i as u8;
+                len += 1;
+            }
+            i += 1;
+        }
+        mask += 1;
+    }
+    table
+};
+
+/// Percent-encodes the bytes not allowed unencoded by the table
+/// and appends the result to `dst`.
+#[target_feature(enable = "avx2,popcnt")]
+pub unsafe fn encode_into(table: impl naive::Table, src: &[u8], dst: &mut Vec<u8>) {
+    encode_into_with_case(table, src, dst, HexCase::Upper);
+}
+
+/// Percent-encodes the bytes not allowed unencoded by the table
+/// with hexadecimal digits in the given case and appends the result to `dst`.
+#[rustc_align(64)]
+#[target_feature(enable = "avx2,popcnt")]
+pub unsafe fn encode_into_with_case(
+    table: impl naive::Table,
+    src: &[u8],
+    dst: &mut Vec<u8>,
+    case: HexCase,
+) {
+    let table = table.to_bitset();
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // % is always encoded
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, (allowed.0 & !(1 << b'%')) as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+
+    let byte_lo_4_mask = _mm256_set1_epi8(0xf);
+    let mask_table = _mm256_set1_epi64x(0x8040201008040201u64 as _);
+    let zero = _mm256_setzero_si256();
+
+    let pct = _mm256_set1_epi8(b'%' as _);
+    let hex_digits = _mm_loadu_si128(case.digits().as_ptr().cast());
+    let hex_digits = _mm256_broadcastsi128_si256(hex_digits);
+
+    let mut i = 0;
+    while i + 32 <= len {
+        // the worst case plus the garbage written by the last full store
+        dst.reserve(32 * 3 + 4);
+        let out = dst.as_mut_ptr().add(dst.len());
+
+        let chunk = _mm256_loadu_si256(ptr.add(i).cast());
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm256_shuffle_epi8(mask_table, chunk);
+        let word_shr_3 = _mm256_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm256_and_si256(word_shr_3, byte_lo_4_mask);
+        let allowed_per_byte = _mm256_shuffle_epi8(allowed, table_idx_per_byte);
+        let nz_if_allowed = _mm256_and_si256(allowed_per_byte, mask_per_byte);
+        let is_disallowed = _mm256_cmpeq_epi8(nz_if_allowed, zero);
+        let is_disallowed = _mm256_movemask_epi8(is_disallowed) as u32;
+
+        if is_disallowed == 0 {
+            _mm256_storeu_si256(out.cast(), chunk);
+            dst.set_len(dst.len() + 32);
+            i += 32;
+            continue;
+        }
+
+        let hi_4 = _mm256_and_si256(_mm256_srli_epi16::<4>(chunk), byte_lo_4_mask);
+        let hexdig_hi = _mm256_shuffle_epi8(hex_digits, hi_4);
+        let lo_4 = _mm256_and_si256(chunk, byte_lo_4_mask);
+        let hexdig_lo = _mm256_shuffle_epi8(hex_digits, lo_4);
+
+        // gather 4 bytes each of the chunk, hexdig_hi, hexdig_lo and %
+        // for groups 0/4, 1/5, 2/6 and 3/7 of 4 bytes, in the lower/upper lane
+        let chunk_hexdig_hi_01 = _mm256_unpacklo_epi32(chunk, hexdig_hi);
+        let chunk_hexdig_hi_23 = _mm256_unpackhi_epi32(chunk, hexdig_hi);
+        let hexdig_lo_pct_01 = _mm256_unpacklo_epi32(hexdig_lo, pct);
+        let hexdig_lo_pct_23 = _mm256_unpackhi_epi32(hexdig_lo, pct);
+        let groups = [
+            _mm256_unpacklo_epi64(chunk_hexdig_hi_01, hexdig_lo_pct_01),
+            _mm256_unpackhi_epi64(chunk_hexdig_hi_01, hexdig_lo_pct_01),
+            _mm256_unpacklo_epi64(chunk_hexdig_hi_23, hexdig_lo_pct_23),
+            _mm256_unpackhi_epi64(chunk_hexdig_hi_23, hexdig_lo_pct_23),
+        ];
+        let groups = [
+            _mm256_castsi256_si128(groups[0]),
+            _mm256_castsi256_si128(groups[1]),
+            _mm256_castsi256_si128(groups[2]),
+            _mm256_castsi256_si128(groups[3]),
+            _mm256_extracti128_si256::<1>(groups[0]),
+            _mm256_extracti128_si256::<1>(groups[1]),
+            _mm256_extracti128_si256::<1>(groups[2]),
+            _mm256_extracti128_si256::<1>(groups[3]),
+        ];
+
+        let mut j = 0;
+        for (k, group) in groups.into_iter().enumerate() {
+            let mask = (is_disallowed >> (k * 4)) as usize & 0xf;
+            let idx = _mm_loadu_si128(ENCODE_TABLE[mask].as_ptr().cast());
+            _mm_storeu_si128(out.add(j).cast(), _mm_shuffle_epi8(group, idx));
+            j += 4 + mask.count_ones() as usize * 2;
+        }
+        dst.set_len(dst.len() + j);
+        i += 32;
+    }
+    super::encode_rest(table, src, i, dst, case);
+}
+
+/// Scans the blocks of 32 bytes from `src[i..]` for query delimiters,
+/// as many as fit in both the string and `masks`, returning their number.
+///
+/// For each block, writes the bitmask of `&`, `;` and `=`, and, if a table
+/// is given, the bitmask of the bytes invalid against it. `after_pct_prev`
+/// carries the bitmask of the bytes following a `%` into the next block.
+#[target_feature(enable = "avx2")]
+pub unsafe fn scan_query(
+    src: &[u8],
+    mut i: usize,
+    table: Option<Table>,
+    after_pct_prev: &mut u64,
+    masks: &mut [(u64, u64)],
+) -> usize {
+    let broadcast = |bits: (u64, u64)| {
+        let table = _mm_set_epi64x(bits.1 as _, bits.0 as _);
+        _mm256_broadcastsi128_si256(table)
+    };
+    let is_zero = |x| _mm256_movemask_epi8(_mm256_cmpeq_epi8(x, _mm256_setzero_si256()));
+
+    let delims = broadcast(crate::query::DELIMITERS.bits());
+    // the corresponding bit for % is set in the first table iff it is allowed
+    let tables = table.map(|table| {
+        let hexdig = super::hexdig_after_pct(table);
+        (broadcast(table.bits()), broadcast(hexdig.bits()))
+    });
+
+    let pct = _mm256_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm256_set1_epi8(0xf);
+    let mask_table = _mm256_set1_epi64x(0x8040201008040201u64 as _);
+    let byte_idx = _mm256_setr_epi64x(
+        0,
+        0x0101010101010101,
+        0x0202020202020202,
+        0x0303030303030303,
+    );
+
+    let mut n = 0;
+    while n < masks.len() && i + 32 <= src.len() {
+        let chunk = _mm256_loadu_si256(src.as_ptr().add(i).cast());
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm256_shuffle_epi8(mask_table, chunk);
+        let word_shr_3 = _mm256_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm256_and_si256(word_shr_3, byte_lo_4_mask);
+
+        let delims_per_byte = _mm256_shuffle_epi8(delims, table_idx_per_byte);
+        let is_delim = !is_zero(_mm256_and_si256(delims_per_byte, mask_per_byte)) as u32 as u64;
+
+        let is_invalid = if let Some((allowed, hexdig)) = tables {
+            let is_pct = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk, pct)) as u32 as u64;
+            let after_pct = ((is_pct << 1) | (is_pct << 2) | *after_pct_prev) as u32;
+            *after_pct_prev = (is_pct >> 30) | (is_pct >> 31);
+
+            // expand the bitmask into bytes
+            let after_pct = _mm256_set1_epi32(after_pct as _);
+            let after_pct = _mm256_shuffle_epi8(after_pct, byte_idx);
+            let after_pct = _mm256_and_si256(after_pct, mask_table);
+            let after_pct = _mm256_cmpeq_epi8(after_pct, mask_table);
+
+            let allowed_per_byte = _mm256_shuffle_epi8(allowed, table_idx_per_byte);
+            let hexdig_per_byte = _mm256_shuffle_epi8(hexdig, table_idx_per_byte);
+            let table_per_byte = _mm256_blendv_epi8(allowed_per_byte, hexdig_per_byte, after_pct);
+
+            is_zero(_mm256_and_si256(table_per_byte, mask_per_byte)) as u32 as u64
+        } else {
+            0
+        };
+
+        masks[n] = (is_delim, is_invalid);
+        n += 1;
         i += 32;
     }
-    i = i.saturating_sub(2);
-    table_bitset::PATH.validate(&src[i..])
+    n
 }
@@ -1,39 +1,69 @@
-use crate::naive::table_bitset;
-use std::arch::x86_64::*;
+use crate::{
+    ValidateError,
+    naive::{
+        self, HexCase, OCTET_TABLE_HI, OCTET_TABLE_LO,
+        table_bitset::{self, Table},
+    },
+};
+use std::{arch::x86_64::*, borrow::Cow, ptr, slice, str};
+
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn validate_3load(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_3load_detailed(table, src).is_ok()
+}
 
 #[rustc_align(64)]
 #[target_feature(enable = "avx512bw")]
-pub unsafe fn validate_3load(src: &[u8]) -> bool {
+pub unsafe fn validate_3load_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
-    let mut i = 0;
-    if len >= 64 + 2 {
-        if !super::validate_first_two(src) {
-            return false;
-        }
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
 
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
-        let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
-        let allowed = _mm256_broadcastsi128_si256(allowed);
-        let allowed = _mm512_broadcast_i64x4(allowed);
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
 
-        let hexdig = table_bitset::HEXDIG.bits();
-        let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
-        let hexdig = _mm256_broadcastsi128_si256(hexdig);
-        let hexdig = _mm512_broadcast_i64x4(hexdig);
+    let find_invalid = |chunk, after_pct| {
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk); // 1 1 1*p5
 
-        let pct = _mm512_set1_epi8(b'%' as _);
-        let byte_lo_4_mask = _mm512_set1_epi8(0xf);
-        let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk); // 1 1 1*p0
+
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask); // 1 0.5 1*p05
+
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // writemasked: 3 1 1*p5
+
+        // this actually translates to writemasked shuffle
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        // this actually sets bit when AND is zero
+        _mm512_testn_epi8_mask(table_per_byte, mask_per_byte) // 3 1 1*p5
+    };
+
+    let mut i = 0;
+    if len >= 64 + 2 {
+        if !super::first_two_valid(table, src) {
+            return super::fail_in_first_two(table, src);
+        }
 
         while i <= len - 64 - 2 {
             let chunk = _mm512_loadu_si512(ptr.add(i + 2).cast()); // <=8 0.5 1*p23
 
-            // for non-ASCII, this is 0
-            let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk); // 1 1 1*p5
-
             // loadu and cmpeq are combined into vpcmpeqb (k, zmm, m512)
             // unlike with AVX2, it is insignificant whether we put them here or below
             let chunk_l1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
@@ -43,63 +73,76 @@ pub unsafe fn validate_3load(src: &[u8]) -> bool {
             let after_pct_2 = _mm512_cmpeq_epi8_mask(chunk_l2, pct); // 3 1 1*p5; with load: n/a
             let after_pct = after_pct_1 | after_pct_2; // korq: 1 1 1*p0
 
-            let word_shr_3 = _mm512_srli_epi16::<3>(chunk); // 1 1 1*p0
-
-            let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask); // 1 0.5 1*p05
-
-            let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
-            let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // writemasked: 3 1 1*p5
-
-            // this actually translates to writemasked shuffle
-            let table_per_byte =
-                _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
-
-            // this actually sets bit when AND is zero
-            let is_invalid = _mm512_testn_epi8_mask(table_per_byte, mask_per_byte); // 3 1 1*p5
+            let is_invalid = find_invalid(chunk, after_pct);
 
             // kortestq: 1 1 1*p0
             if is_invalid != 0 {
-                return false;
+                let j = i + 2 + is_invalid.trailing_zeros() as usize;
+                return super::fail_at(table, src, j);
             }
             i += 64;
         }
+        // the loop has validated `src[..i + 2]`
+        i += 2;
     }
-    table_bitset::PATH.validate(&src[i..])
+    validate_tail(table, src, i, find_invalid)
+}
+
+#[target_feature(enable = "avx512bw,gfni")]
+pub unsafe fn validate_3load_gf2p8affine(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_3load_gf2p8affine_detailed(table, src).is_ok()
 }
 
 #[rustc_align(64)]
 #[target_feature(enable = "avx512bw,gfni")]
-pub unsafe fn validate_3load_gf2p8affine(src: &[u8]) -> bool {
+pub unsafe fn validate_3load_gf2p8affine_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
-    let mut i = 0;
-    if len >= 64 + 2 {
-        if !super::validate_first_two(src) {
-            return false;
-        }
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
 
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
-        let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
-        let allowed = _mm256_broadcastsi128_si256(allowed);
-        let allowed = _mm512_broadcast_i64x4(allowed);
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
 
-        let hexdig = table_bitset::HEXDIG.bits();
-        let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
-        let hexdig = _mm256_broadcastsi128_si256(hexdig);
-        let hexdig = _mm512_broadcast_i64x4(hexdig);
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let srl_3_matrix = _mm512_set1_epi64(0x0102040810204080 << 3);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
 
-        let pct = _mm512_set1_epi8(b'%' as _);
-        let srl_3_matrix = _mm512_set1_epi64(0x0102040810204080 << 3);
-        let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+    let find_invalid = |chunk, after_pct| {
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk); // 1 1 1*p5
+
+        let table_idx_per_byte = _mm512_gf2p8affine_epi64_epi8::<0>(chunk, srl_3_matrix); // 5 1 1*p0
+
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // writemasked: 3 1 1*p5
+
+        // this actually translates to writemasked shuffle
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        // this actually sets bit when AND is zero
+        _mm512_testn_epi8_mask(table_per_byte, mask_per_byte) // 3 1 1*p5
+    };
+
+    let mut i = 0;
+    if len >= 64 + 2 {
+        if !super::first_two_valid(table, src) {
+            return super::fail_in_first_two(table, src);
+        }
 
         while i <= len - 64 - 2 {
             let chunk = _mm512_loadu_si512(ptr.add(i + 2).cast()); // <=8 0.5 1*p23
 
-            // for non-ASCII, this is 0
-            let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk); // 1 1 1*p5
-
             // loadu and cmpeq are combined into vpcmpeqb (k, zmm, m512)
             // unlike with AVX2, it is insignificant whether we put them here or below
             let chunk_l1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
@@ -109,55 +152,72 @@ pub unsafe fn validate_3load_gf2p8affine(src: &[u8]) -> bool {
             let after_pct_2 = _mm512_cmpeq_epi8_mask(chunk_l2, pct); // 3 1 1*p5; with load: n/a
             let after_pct = after_pct_1 | after_pct_2; // korq: 1 1 1*p0
 
-            let table_idx_per_byte = _mm512_gf2p8affine_epi64_epi8::<0>(chunk, srl_3_matrix); // 5 1 1*p0
-
-            let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
-            let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // writemasked: 3 1 1*p5
-
-            // this actually translates to writemasked shuffle
-            let table_per_byte =
-                _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
-
-            // this actually sets bit when AND is zero
-            let is_invalid = _mm512_testn_epi8_mask(table_per_byte, mask_per_byte); // 3 1 1*p5
+            let is_invalid = find_invalid(chunk, after_pct);
 
             // kortestq: 1 1 1*p0
             if is_invalid != 0 {
-                return false;
+                let j = i + 2 + is_invalid.trailing_zeros() as usize;
+                return super::fail_at(table, src, j);
             }
             i += 64;
         }
+        // the loop has validated `src[..i + 2]`
+        i += 2;
     }
-    table_bitset::PATH.validate(&src[i..])
+    validate_tail(table, src, i, find_invalid)
+}
+
+#[target_feature(enable = "avx512bw,avx512vbmi")]
+pub unsafe fn validate_3load_perm(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_3load_perm_detailed(table, src).is_ok()
 }
 
 #[rustc_align(64)]
 #[target_feature(enable = "avx512bw,avx512vbmi")]
-pub unsafe fn validate_3load_perm(src: &[u8]) -> bool {
+pub unsafe fn validate_3load_perm_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
-    let mut i = 0;
-    if len >= 64 + 2 {
-        if !super::validate_first_two(src) {
-            return false;
-        }
+    let mut table_lo = _mm512_setzero_si512();
+    let mut table_hi = _mm512_setzero_si512();
+    let mask_hexdig = _mm512_set1_epi8(1);
+    let mask_allowed = _mm512_set1_epi8(2);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    table_lo = _mm512_mask_add_epi8(table_lo, hexdig.0, table_lo, mask_hexdig);
+    table_hi = _mm512_mask_add_epi8(table_hi, hexdig.1, table_hi, mask_hexdig);
+
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    table_lo = _mm512_mask_add_epi8(table_lo, allowed.0, table_lo, mask_allowed);
+    table_hi = _mm512_mask_add_epi8(table_hi, allowed.1, table_hi, mask_allowed);
 
-        let mut table_lo = _mm512_setzero_si512();
-        let mut table_hi = _mm512_setzero_si512();
-        let mask_hexdig = _mm512_set1_epi8(1);
-        let mask_allowed = _mm512_set1_epi8(2);
+    let pct = _mm512_set1_epi8(b'%' as _);
 
-        let hexdig = table_bitset::HEXDIG.bits();
-        table_lo = _mm512_mask_add_epi8(table_lo, hexdig.0, table_lo, mask_hexdig);
-        table_hi = _mm512_mask_add_epi8(table_hi, hexdig.1, table_hi, mask_hexdig);
+    let find_invalid = |chunk, after_pct| {
+        // cmpge_epi8 turns into vpmovb2m+knotq, while
+        // movepi8_mask turns into vpcmpb, which is odd
+        let is_ascii = !_mm512_movepi8_mask(chunk); // vpcmpb: 3 1 1*p5
 
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
-        table_lo = _mm512_mask_add_epi8(table_lo, allowed.0, table_lo, mask_allowed);
-        table_hi = _mm512_mask_add_epi8(table_hi, allowed.1, table_hi, mask_allowed);
+        let table_per_byte = _mm512_maskz_permutex2var_epi8(is_ascii, table_lo, chunk, table_hi); // 6 2 1*p05+2*p5
 
-        let pct = _mm512_set1_epi8(b'%' as _);
+        // this turns into vpblendmd (zmm, k, zmm, m512)
+        // which benches faster than (.., zmm) I really don't know why
+        let mask_per_byte = _mm512_mask_blend_epi8(after_pct, mask_allowed, mask_hexdig); // <=11 0.5 1*p05+1*p23
+
+        // this actually sets bit when AND is zero
+        _mm512_testn_epi8_mask(table_per_byte, mask_per_byte) // 3 1 1*p5
+    };
+
+    let mut i = 0;
+    if len >= 64 + 2 {
+        if !super::first_two_valid(table, src) {
+            return super::fail_in_first_two(table, src);
+        }
 
         while i <= len - 64 - 2 {
             let chunk = _mm512_loadu_si512(ptr.add(i + 2).cast()); // <=8 0.5 1*p23
@@ -170,26 +230,1269 @@ pub unsafe fn validate_3load_perm(src: &[u8]) -> bool {
             let after_pct_2 = _mm512_cmpeq_epi8_mask(chunk_l2, pct); // 3 1 1*p5; with load: n/a
             let after_pct = after_pct_1 | after_pct_2; // korq: 1 1 1*p0
 
-            // cmpge_epi8 turns into vpmovb2m+knotq, while
-            // movepi8_mask turns into vpcmpb, which is odd
-            let is_ascii = !_mm512_movepi8_mask(chunk); // vpcmpb: 3 1 1*p5
+            let is_invalid = find_invalid(chunk, after_pct);
 
-            let table_per_byte =
-                _mm512_maskz_permutex2var_epi8(is_ascii, table_lo, chunk, table_hi); // 6 2 1*p05+2*p5
+            // kortestq: 1 1 1*p0
+            if is_invalid != 0 {
+                let j = i + 2 + is_invalid.trailing_zeros() as usize;
+                return super::fail_at(table, src, j);
+            }
+            i += 64;
+        }
+        // the loop has validated `src[..i + 2]`
+        i += 2;
+    }
+    validate_tail(table, src, i, find_invalid)
+}
 
-            // this turns into vpblendmd (zmm, k, zmm, m512)
-            // which benches faster than (.., zmm) I really don't know why
-            let mask_per_byte = _mm512_mask_blend_epi8(after_pct, mask_allowed, mask_hexdig); // <=11 0.5 1*p05+1*p23
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn validate_alignr(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_alignr_detailed(table, src).is_ok()
+}
 
-            // this actually sets bit when AND is zero
-            let is_invalid = _mm512_testn_epi8_mask(table_per_byte, mask_per_byte); // 3 1 1*p5
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn validate_alignr_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    let mut i = 0;
+    if len >= 128 {
+        let mut chunk = _mm512_loadu_si512(ptr.cast());
+
+        // for non-ASCII, this is 0
+        let mut mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk);
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk);
+        let mut table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte);
+        let mut nz_if_hexdig = _mm512_and_si512(hexdig_per_byte, mask_per_byte);
+
+        while i + 128 <= len {
+            let next_chunk = _mm512_loadu_si512(ptr.add(i + 64).cast()); // <=8 0.5 1*p23
+
+            let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct); // 3 1 1*p5
+
+            let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
+            let nz_if_allowed = _mm512_and_si512(allowed_per_byte, mask_per_byte); // 1 0.5 1*p05
+
+            mask_per_byte = _mm512_shuffle_epi8(mask_table, next_chunk); // 1 1 1*p5
+            let word_shr_3 = _mm512_srli_epi16::<3>(next_chunk); // 1 1 1*p0
+            table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask); // 1 0.5 1*p05
+            let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // 1 1 1*p5
+            let next_nz_if_hexdig = _mm512_and_si512(hexdig_per_byte, mask_per_byte); // 1 0.5 1*p05
+
+            // valignd shifts across the 128-bit lanes, which palignr cannot
+            let nz_if_hexdig_16 = _mm512_alignr_epi32::<4>(next_nz_if_hexdig, nz_if_hexdig); // 3 1 1*p5
+            let nz_if_hexdig_1 = _mm512_alignr_epi8::<1>(nz_if_hexdig_16, nz_if_hexdig); // 1 1 1*p5
+            let nz_if_hexdig_2 = _mm512_alignr_epi8::<2>(nz_if_hexdig_16, nz_if_hexdig); // 1 1 1*p5
+            let nz_if_hexdig_1_and_2 = _mm512_min_epu8(nz_if_hexdig_1, nz_if_hexdig_2); // 1 0.5 1*p05
+
+            let nz_if_valid = _mm512_mask_blend_epi8(is_pct, nz_if_allowed, nz_if_hexdig_1_and_2); // 1 0.5 1*p05
+
+            let is_invalid = _mm512_testn_epi8_mask(nz_if_valid, nz_if_valid); // 3 1 1*p5
 
-            // kortestq: 1 1 1*p0
             if is_invalid != 0 {
+                // bytes following a % are checked to be hexdig above
+                // but not necessarily allowed by the table, so exclude them
+                let after_pct = super::after_pct_mask(src, i, is_pct);
+                let is_invalid = is_invalid & !after_pct;
+                if is_invalid != 0 {
+                    let j = i + is_invalid.trailing_zeros() as usize;
+                    return super::fail_at(table, src, j);
+                }
+            }
+
+            chunk = next_chunk;
+            nz_if_hexdig = next_nz_if_hexdig;
+            i += 64;
+        }
+    }
+
+    let find_invalid = |chunk, after_pct| {
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk); // 1 1 1*p5
+
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk); // 1 1 1*p0
+
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask); // 1 0.5 1*p05
+
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // writemasked: 3 1 1*p5
+
+        // this actually translates to writemasked shuffle
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        // this actually sets bit when AND is zero
+        _mm512_testn_epi8_mask(table_per_byte, mask_per_byte) // 3 1 1*p5
+    };
+    validate_tail(table, src, i, find_invalid)
+}
+
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn validate_alignl(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_alignl_detailed(table, src).is_ok()
+}
+
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn validate_alignl_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    let find_invalid = |chunk, after_pct| {
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk); // 1 1 1*p5
+
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk); // 1 1 1*p0
+
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask); // 1 0.5 1*p05
+
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte); // 1 1 1*p5
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte); // writemasked: 3 1 1*p5
+
+        // this actually translates to writemasked shuffle
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        // this actually sets bit when AND is zero
+        _mm512_testn_epi8_mask(table_per_byte, mask_per_byte) // 3 1 1*p5
+    };
+
+    // bits set for the first two bytes of a chunk that follow a % in the previous chunk
+    let mut after_pct_prev = 0u64;
+
+    let mut i = 0;
+    while i + 64 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast()); // <=8 0.5 1*p23
+
+        // the % mask is shifted instead of the bytes,
+        // so no lane-crossing shuffle is needed
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct); // 3 1 1*p5
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev; // kshiftlq: 4 1 1*p5
+        after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+
+        let is_invalid = find_invalid(chunk, after_pct);
+
+        if is_invalid != 0 {
+            let j = i + is_invalid.trailing_zeros() as usize;
+            return super::fail_at(table, src, j);
+        }
+        i += 64;
+    }
+    validate_tail(table, src, i, find_invalid)
+}
+
+/// Validates each of the strings with the table, returning whether each is valid.
+///
+/// Strings of up to 64 bytes are packed together into blocks, so that short
+/// strings are validated several at a time. The look-behind for `%` is masked
+/// at the start of each string, so that it never crosses between strings.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi2")]
+pub unsafe fn validate_many(table: impl naive::Table, srcs: &[&[u8]]) -> Vec<bool> {
+    let table = table.to_bitset();
+
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    // returns the bitmask of the bytes at `[off, off + len)`
+    let span = |off: usize, len: usize| match len {
+        0 => 0,
+        _ => (u64::MAX >> (64 - len)) << off,
+    };
+
+    let mut valid = Vec::with_capacity(srcs.len());
+    let mut rest = srcs;
+    while let [first, ..] = rest {
+        if first.len() > 64 {
+            valid.push(validate_3load(table, first));
+            rest = &rest[1..];
+            continue;
+        }
+
+        let mut chunk = _mm512_setzero_si512();
+        // bits set for the first and the last byte of each string
+        let (mut starts, mut ends) = (0, 0);
+        let (mut n, mut off) = (0, 0);
+        for src in rest {
+            if src.len() > 64 - off {
+                break;
+            }
+            if !src.is_empty() {
+                // reads exactly `src.len()` bytes, into the lanes starting at `off`
+                chunk =
+                    _mm512_mask_expandloadu_epi8(chunk, span(off, src.len()), src.as_ptr().cast());
+                starts |= 1 << off;
+                ends |= 1 << (off + src.len() - 1);
+            }
+            off += src.len();
+            n += 1;
+        }
+
+        // a byte does not follow a % in another string
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+        let after_pct_1 = (is_pct << 1) & !starts;
+        let after_pct_2 = (is_pct << 2) & !starts & !(starts << 1);
+        let after_pct = after_pct_1 | after_pct_2;
+
+        // a % is truncated if it is among the last two bytes of a string,
+        // where the byte before a one-byte string is the last of another
+        let is_truncated = is_pct & (ends | (ends >> 1));
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk);
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte);
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte);
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        // this actually sets bit when AND is zero
+        let is_invalid = _mm512_testn_epi8_mask(table_per_byte, mask_per_byte) | is_truncated;
+
+        let mut off = 0;
+        for src in &rest[..n] {
+            // non-ASCII bytes are flagged regardless of the table
+            let ok = is_invalid & span(off, src.len()) == 0
+                || table.allows_non_ascii() && table.validate(src);
+            valid.push(ok);
+            off += src.len();
+        }
+        rest = &rest[n..];
+    }
+    valid
+}
+
+/// Validates `src[i..]` with masked loads, given that `src[..i]` is valid.
+///
+/// `find_invalid` returns the bitmask of the invalid bytes in a block,
+/// given the bitmask of the bytes that follow a `%` within two bytes.
+#[inline]
+#[target_feature(enable = "avx512bw")]
+unsafe fn validate_tail(
+    table: Table,
+    src: &[u8],
+    mut i: usize,
+    find_invalid: impl Fn(__m512i, u64) -> u64,
+) -> Result<(), ValidateError> {
+    let len = src.len();
+    let pct = _mm512_set1_epi8(b'%' as _);
+
+    // bits set for the first two bytes of a block that follow a `%` before it
+    let mut after_pct_prev = match src[..i] {
+        [.., b'%'] => 0b11,
+        [.., b'%', _] => 0b01,
+        _ => 0,
+    };
+
+    // the callers leave less than 128 bytes, so at most two iterations
+    while i < len {
+        let n = (len - i).min(64);
+        let in_bounds = u64::MAX >> (64 - n);
+
+        // the bytes out of bounds are neither loaded nor faulted on
+        let chunk = _mm512_maskz_loadu_epi8(in_bounds, src.as_ptr().add(i).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev;
+        after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+
+        let is_invalid = find_invalid(chunk, after_pct) & in_bounds;
+        if is_invalid != 0 {
+            let j = i + is_invalid.trailing_zeros() as usize;
+            return super::fail_at(table, src, j);
+        }
+        i += n;
+    }
+
+    // a triplet may be truncated by the end of the string
+    match src {
+        [.., b'%'] | [.., b'%', _] => super::validate_rest(table, src, len),
+        _ => Ok(()),
+    }
+}
+
+// Error bits for UTF-8 validation, from "Validating UTF-8 In Less Than One
+// Instruction Per Byte" by John Keiser and Daniel Lemire.
+const TOO_SHORT: u8 = 1 << 0;
+const TOO_LONG: u8 = 1 << 1;
+const OVERLONG_3: u8 = 1 << 2;
+const TOO_LARGE: u8 = 1 << 3;
+const SURROGATE: u8 = 1 << 4;
+const OVERLONG_2: u8 = 1 << 5;
+const TOO_LARGE_1000: u8 = 1 << 6;
+const OVERLONG_4: u8 = 1 << 6;
+const TWO_CONTS: u8 = 1 << 7;
+const CARRY: u8 = TOO_SHORT | TOO_LONG | TWO_CONTS;
+
+/// Errors possible with the high nibble of the first byte.
+const UTF8_BYTE_1_HIGH: [u8; 16] = [
+    // 0_______: ASCII
+    TOO_LONG,
+    TOO_LONG,
+    TOO_LONG,
+    TOO_LONG,
+    TOO_LONG,
+    TOO_LONG,
+    TOO_LONG,
+    TOO_LONG,
+    // 10______: continuation
+    TWO_CONTS,
+    TWO_CONTS,
+    TWO_CONTS,
+    TWO_CONTS,
+    // 1100____: two-byte lead
+    TOO_SHORT | OVERLONG_2,
+    // 1101____: two-byte lead
+    TOO_SHORT,
+    // 1110____: three-byte lead
+    TOO_SHORT | OVERLONG_3 | SURROGATE,
+    // 1111____: four-byte lead
+    TOO_SHORT | TOO_LARGE | TOO_LARGE_1000 | OVERLONG_4,
+];
+
+/// Errors possible with the low nibble of the first byte.
+const UTF8_BYTE_1_LOW: [u8; 16] = [
+    CARRY | OVERLONG_3 | OVERLONG_2 | OVERLONG_4,
+    CARRY | OVERLONG_2,
+    CARRY,
+    CARRY,
+    CARRY | TOO_LARGE,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000 | SURROGATE,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+];
+
+/// Errors possible with the high nibble of the second byte.
+const UTF8_BYTE_2_HIGH: [u8; 16] = [
+    // 0_______: ASCII
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    // 1000____
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | OVERLONG_3 | TOO_LARGE_1000 | OVERLONG_4,
+    // 1001____
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | OVERLONG_3 | TOO_LARGE,
+    // 101_____
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | SURROGATE | TOO_LARGE,
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | SURROGATE | TOO_LARGE,
+    // 11______: lead
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+    TOO_SHORT,
+];
+
+/// Permutation indices that select, for each byte of a block,
+/// the byte `K` positions before it from the block and the previous one.
+const fn prev_idx<const K: usize>() -> [u8; 64] {
+    let mut idx = [0; 64];
+    let mut j = 0;
+    while j < 64 {
+        idx[j] = ((j + 128 - K) & 127) as u8;
+        j += 1;
+    }
+    idx
+}
+
+const PREV_IDX_1: [u8; 64] = prev_idx::<1>();
+const PREV_IDX_2: [u8; 64] = prev_idx::<2>();
+const PREV_IDX_3: [u8; 64] = prev_idx::<3>();
+
+/// Returns the bitmask of the bytes at which a UTF-8 sequence is found
+/// ill-formed, given the bytes 1, 2 and 3 positions before each byte.
+///
+/// A sequence cut short at the end of the block is not yet found ill-formed.
+#[inline]
+#[target_feature(enable = "avx512bw")]
+unsafe fn utf8_ill_formed(chunk: __m512i, prev1: __m512i, prev2: __m512i, prev3: __m512i) -> u64 {
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let byte_1_high = _mm512_broadcast_i32x4(_mm_loadu_si128(UTF8_BYTE_1_HIGH.as_ptr().cast()));
+    let byte_1_low = _mm512_broadcast_i32x4(_mm_loadu_si128(UTF8_BYTE_1_LOW.as_ptr().cast()));
+    let byte_2_high = _mm512_broadcast_i32x4(_mm_loadu_si128(UTF8_BYTE_2_HIGH.as_ptr().cast()));
+
+    let prev1_hi = _mm512_and_si512(_mm512_srli_epi16::<4>(prev1), byte_lo_4_mask);
+    let prev1_lo = _mm512_and_si512(prev1, byte_lo_4_mask);
+    let chunk_hi = _mm512_and_si512(_mm512_srli_epi16::<4>(chunk), byte_lo_4_mask);
+
+    let special_cases = _mm512_and_si512(
+        _mm512_and_si512(
+            _mm512_shuffle_epi8(byte_1_high, prev1_hi),
+            _mm512_shuffle_epi8(byte_1_low, prev1_lo),
+        ),
+        _mm512_shuffle_epi8(byte_2_high, chunk_hi),
+    );
+
+    // the third and fourth bytes of a sequence must be continuations
+    let must_be_cont = _mm512_cmpge_epu8_mask(prev2, _mm512_set1_epi8(0xe0u8 as _))
+        | _mm512_cmpge_epu8_mask(prev3, _mm512_set1_epi8(0xf0u8 as _));
+    let must_be_cont = _mm512_maskz_mov_epi8(must_be_cont, _mm512_set1_epi8(TWO_CONTS as _));
+    let errors = _mm512_xor_si512(special_cases, must_be_cont);
+    _mm512_test_epi8_mask(errors, errors)
+}
+
+#[target_feature(enable = "avx512bw,avx512vbmi")]
+pub unsafe fn validate_iri(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_iri_detailed(table, src).is_ok()
+}
+
+/// Validates the string with a table that may allow non-ASCII characters,
+/// checking that they are well-formed UTF-8 and allowed by the table.
+///
+/// The UTF-8 checks are skipped for blocks with no non-ASCII byte.
+/// On failure, the exact error is found by the scalar implementation.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi")]
+pub unsafe fn validate_iri_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    let prev_idx_1 = _mm512_loadu_si512(PREV_IDX_1.as_ptr().cast());
+    let prev_idx_2 = _mm512_loadu_si512(PREV_IDX_2.as_ptr().cast());
+    let prev_idx_3 = _mm512_loadu_si512(PREV_IDX_3.as_ptr().cast());
+
+    let set1 = |x: u8| _mm512_set1_epi8(x as _);
+
+    let mut prev_chunk = _mm512_setzero_si512();
+    let mut after_pct_prev = 0u64;
+    let mut is_non_ascii_prev = 0u64;
+
+    let mut i = 0;
+    while i + 64 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev;
+        let is_non_ascii = _mm512_movepi8_mask(chunk);
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk);
+
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte);
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte);
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        // non-ASCII bytes not following a % are checked below
+        let mut is_invalid =
+            _mm512_mask_testn_epi8_mask(!is_non_ascii | after_pct, table_per_byte, mask_per_byte);
+
+        // a sequence may straddle the previous block only if it ends with non-ASCII
+        if is_non_ascii | (is_non_ascii_prev >> 63) != 0 {
+            let prev1 = _mm512_permutex2var_epi8(chunk, prev_idx_1, prev_chunk);
+            let prev2 = _mm512_permutex2var_epi8(chunk, prev_idx_2, prev_chunk);
+            let prev3 = _mm512_permutex2var_epi8(chunk, prev_idx_3, prev_chunk);
+
+            let is_ill_formed = utf8_ill_formed(chunk, prev1, prev2, prev3);
+
+            // the remaining checks assume well-formedness, and look at
+            // the second byte of a sequence or later
+            let prev1_eq = |x| _mm512_cmpeq_epi8_mask(prev1, set1(x));
+            let prev2_eq = |x| _mm512_cmpeq_epi8_mask(prev2, set1(x));
+            let chunk_ge = |x| _mm512_cmpge_epu8_mask(chunk, set1(x));
+
+            // U+0080 to U+009F
+            let is_c1 = prev1_eq(0xc2) & !chunk_ge(0xa0);
+            // U+FDD0 to U+FDEF
+            let is_fdd0 = prev2_eq(0xef) & prev1_eq(0xb7) & chunk_ge(0x90) & !chunk_ge(0xb0);
+            // U+FFF0 to U+FFFF
+            let is_fff0 = prev2_eq(0xef) & prev1_eq(0xbf) & chunk_ge(0xb0);
+            // U+xFFFE and U+xFFFF in the supplementary planes
+            let is_nonchar = _mm512_cmpge_epu8_mask(prev3, set1(0xf0))
+                & _mm512_cmpeq_epi8_mask(_mm512_and_si512(prev2, byte_lo_4_mask), byte_lo_4_mask)
+                & prev1_eq(0xbf)
+                & chunk_ge(0xbe);
+            // U+E0000 to U+E0FFF
+            let is_e0000 = prev1_eq(0xf3) & _mm512_cmpeq_epi8_mask(chunk, set1(0xa0));
+
+            let mut is_disallowed = is_c1 | is_fdd0 | is_fff0 | is_nonchar | is_e0000;
+
+            // U+E000 to U+F8FF, U+F0000 to U+FFFFD, and U+100000 to U+10FFFD
+            let is_private = prev1_eq(0xee)
+                | (prev1_eq(0xef) & !chunk_ge(0xa4))
+                | (prev1_eq(0xf3) & chunk_ge(0xb0))
+                | prev1_eq(0xf4);
+            if !table.allows_iprivate() {
+                is_disallowed |= is_private;
+            }
+            if !table.allows_ucschar() {
+                let is_second = _mm512_cmpge_epu8_mask(prev1, set1(0xc0));
+                is_disallowed |= is_second & !is_private;
+            }
+
+            is_invalid |= is_ill_formed | is_disallowed;
+        }
+
+        if is_invalid != 0 {
+            return super::validate_rest(table, src, i);
+        }
+
+        after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+        is_non_ascii_prev = is_non_ascii;
+        prev_chunk = chunk;
+        i += 64;
+    }
+    super::validate_rest(table, src, i)
+}
+
+/// Decodes a percent-encoded string into `dst`, returning the number of bytes written.
+///
+/// # Safety
+///
+/// `src` must be properly percent-encoded, that is, every `%` in it must be followed
+/// by two hexadecimal digits. `dst` must be valid for writes of `src.len()` bytes.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2")]
+pub unsafe fn decode(src: &[u8], dst: *mut u8) -> usize {
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // the lower halves of the tables suffice for hexdig
+    let octet_hi_lo = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().cast());
+    let octet_hi_hi = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().add(64).cast());
+    let octet_lo_lo = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().cast());
+    let octet_lo_hi = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().add(64).cast());
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+
+    // bits set for the first two bytes of a chunk that follow a % in the previous chunk
+    let mut after_pct_prev = 0u64;
+
+    let mut i = 0;
+    let mut j = 0;
+    while i + 64 + 2 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+        let chunk_r1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
+        let chunk_r2 = _mm512_loadu_si512(ptr.add(i + 2).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+
+        // the indices are taken modulo 128, which is fine for hexdig
+        let octet_hi = _mm512_permutex2var_epi8(octet_hi_lo, chunk_r1, octet_hi_hi);
+        let octet_lo = _mm512_permutex2var_epi8(octet_lo_lo, chunk_r2, octet_lo_hi);
+        let octet = _mm512_or_si512(octet_hi, octet_lo);
+
+        // replace each % with the octet it starts
+        let decoded = _mm512_mask_mov_epi8(chunk, is_pct, octet);
+
+        // and drop the two hexdig following it
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev;
+        after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+
+        let compressed = _mm512_maskz_compress_epi8(!after_pct, decoded);
+        _mm512_storeu_si512(dst.add(j).cast(), compressed);
+
+        j += (!after_pct).count_ones() as usize;
+        i += 64;
+    }
+    i += after_pct_prev.count_ones() as usize;
+    j + super::decode_rest(src, i, dst.add(j))
+}
+
+/// Decodes the percent-encoded octets in the buffer in place like
+/// [`naive::decode_in_place`], returning the decoded length.
+///
+/// The decoded bytes of each block are compress-stored toward the front,
+/// never past the block being read.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2")]
+pub unsafe fn decode_in_place(buf: &mut [u8]) -> usize {
+    let len = buf.len();
+    let ptr = buf.as_mut_ptr();
+
+    let hexdig = table_bitset::HEXDIG.bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    // the lower halves of the tables suffice for hexdig
+    let octet_hi_lo = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().cast());
+    let octet_hi_hi = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().add(64).cast());
+    let octet_lo_lo = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().cast());
+    let octet_lo_hi = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().add(64).cast());
+
+    // returns the bitmask of the hexadecimal digits in `x`
+    let is_hexdig = |x| {
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, x);
+        let word_shr_3 = _mm512_srli_epi16::<3>(x);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+        let table_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte);
+        _mm512_test_epi8_mask(table_per_byte, mask_per_byte)
+    };
+
+    // bits set for the first two bytes of a chunk that follow a triplet in the previous chunk
+    let mut after_triplet_prev = 0u64;
+
+    let mut i = 0;
+    let mut j = 0;
+    while i + 64 + 2 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+        let chunk_r1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
+        let chunk_r2 = _mm512_loadu_si512(ptr.add(i + 2).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+        let is_triplet = is_pct & is_hexdig(chunk_r1) & is_hexdig(chunk_r2);
+        let after_triplet = (is_triplet << 1) | (is_triplet << 2) | after_triplet_prev;
+
+        if j == i && is_triplet | after_triplet == 0 {
+            // nothing to move yet
+            j += 64;
+        } else {
+            let octet_hi = _mm512_permutex2var_epi8(octet_hi_lo, chunk_r1, octet_hi_hi);
+            let octet_lo = _mm512_permutex2var_epi8(octet_lo_lo, chunk_r2, octet_lo_hi);
+            let octet = _mm512_or_si512(octet_hi, octet_lo);
+            let decoded = _mm512_mask_mov_epi8(chunk, is_triplet, octet);
+
+            // a full store could overwrite the bytes not yet read
+            _mm512_mask_compressstoreu_epi8(ptr.add(j).cast(), !after_triplet, decoded);
+            j += (!after_triplet).count_ones() as usize;
+        }
+
+        after_triplet_prev = (is_triplet >> 62) | (is_triplet >> 63);
+        i += 64;
+    }
+
+    // skip the rest of the straddling triplet
+    i += after_triplet_prev.count_ones() as usize;
+    buf.copy_within(i.., j);
+    j + naive::decode_in_place(&mut buf[j..j + len - i])
+}
+
+/// Checks whether the bytes are valid UTF-8.
+///
+/// The checks are skipped for blocks with no non-ASCII byte.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi")]
+pub unsafe fn validate_utf8(src: &[u8]) -> bool {
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    let prev_idx_1 = _mm512_loadu_si512(PREV_IDX_1.as_ptr().cast());
+    let prev_idx_2 = _mm512_loadu_si512(PREV_IDX_2.as_ptr().cast());
+    let prev_idx_3 = _mm512_loadu_si512(PREV_IDX_3.as_ptr().cast());
+
+    let mut prev_chunk = _mm512_setzero_si512();
+    let mut is_non_ascii_prev = 0u64;
+
+    let mut i = 0;
+    while i + 64 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+        let is_non_ascii = _mm512_movepi8_mask(chunk);
+
+        // a sequence may straddle the previous block only if it ends with non-ASCII
+        if is_non_ascii | (is_non_ascii_prev >> 63) != 0 {
+            let prev1 = _mm512_permutex2var_epi8(chunk, prev_idx_1, prev_chunk);
+            let prev2 = _mm512_permutex2var_epi8(chunk, prev_idx_2, prev_chunk);
+            let prev3 = _mm512_permutex2var_epi8(chunk, prev_idx_3, prev_chunk);
+            if utf8_ill_formed(chunk, prev1, prev2, prev3) != 0 {
                 return false;
             }
+        }
+
+        is_non_ascii_prev = is_non_ascii;
+        prev_chunk = chunk;
+        i += 64;
+    }
+
+    let i = naive::utf8::floor_seq_start(src, i);
+    str::from_utf8(&src[i..]).is_ok()
+}
+
+/// Decodes a percent-encoded string into `dst` like [`decode`],
+/// checking in the same pass whether the bytes written are valid UTF-8.
+///
+/// Returns the number of bytes written, and whether they are valid UTF-8,
+/// or `None` if `src` is not properly percent-encoded, in which case
+/// the contents of `dst` are unspecified.
+///
+/// # Safety
+///
+/// `dst` must be valid for writes of `src.len()` bytes.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2")]
+pub unsafe fn decode_utf8(src: &[u8], dst: *mut u8) -> Option<(usize, bool)> {
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // the lower halves of the tables suffice for hexdig
+    let octet_hi_lo = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().cast());
+    let octet_hi_hi = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().add(64).cast());
+    let octet_lo_lo = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().cast());
+    let octet_lo_hi = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().add(64).cast());
+
+    let prev_idx_1 = _mm512_loadu_si512(PREV_IDX_1.as_ptr().cast());
+    let prev_idx_2 = _mm512_loadu_si512(PREV_IDX_2.as_ptr().cast());
+    let prev_idx_3 = _mm512_loadu_si512(PREV_IDX_3.as_ptr().cast());
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+
+    // bits set for the first two bytes of a chunk that follow a % in the previous chunk
+    let mut after_pct_prev = 0u64;
+
+    // the output is checked in blocks of 64 bytes as soon as they are written,
+    // while they are still in the cache
+    let mut checked = 0;
+    let mut prev_out = _mm512_setzero_si512();
+    let mut is_non_ascii_prev = 0u64;
+    let mut is_ill_formed = 0u64;
+    // bits set for the `%` not followed by two hexadecimal digits
+    let mut is_malformed = 0u64;
+
+    let mut i = 0;
+    let mut j = 0;
+    while i + 64 + 2 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+        let chunk_r1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
+        let chunk_r2 = _mm512_loadu_si512(ptr.add(i + 2).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+
+        // the indices are taken modulo 128, which is fine for hexdig
+        let octet_hi = _mm512_permutex2var_epi8(octet_hi_lo, chunk_r1, octet_hi_hi);
+        let octet_lo = _mm512_permutex2var_epi8(octet_lo_lo, chunk_r2, octet_lo_hi);
+        let octet = _mm512_or_si512(octet_hi, octet_lo);
+
+        // a non-hexdig is looked up as 0xff, but a non-ASCII byte may be mistaken for hexdig
+        let octet_lo_r1 = _mm512_permutex2var_epi8(octet_lo_lo, chunk_r1, octet_lo_hi);
+        let looked_up = _mm512_or_si512(octet_lo_r1, octet_lo);
+        let raw = _mm512_or_si512(chunk_r1, chunk_r2);
+        is_malformed |= is_pct & _mm512_movepi8_mask(_mm512_or_si512(looked_up, raw));
+
+        // replace each % with the octet it starts
+        let decoded = _mm512_mask_mov_epi8(chunk, is_pct, octet);
+
+        // and drop the two hexdig following it
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev;
+        after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+
+        let compressed = _mm512_maskz_compress_epi8(!after_pct, decoded);
+        _mm512_storeu_si512(dst.add(j).cast(), compressed);
+
+        j += (!after_pct).count_ones() as usize;
+        i += 64;
+
+        // at most 64 bytes are written per iteration
+        if checked + 64 <= j {
+            let out = _mm512_loadu_si512(dst.add(checked).cast());
+            let is_non_ascii = _mm512_movepi8_mask(out);
+
+            // a sequence may straddle the previous block only if it ends with non-ASCII
+            if is_non_ascii | (is_non_ascii_prev >> 63) != 0 {
+                let prev1 = _mm512_permutex2var_epi8(out, prev_idx_1, prev_out);
+                let prev2 = _mm512_permutex2var_epi8(out, prev_idx_2, prev_out);
+                let prev3 = _mm512_permutex2var_epi8(out, prev_idx_3, prev_out);
+                is_ill_formed |= utf8_ill_formed(out, prev1, prev2, prev3);
+            }
+
+            is_non_ascii_prev = is_non_ascii;
+            prev_out = out;
+            checked += 64;
+        }
+    }
+    i += after_pct_prev.count_ones() as usize;
+    if is_malformed != 0 || !is_pct_encoded(&src[i..]) {
+        return None;
+    }
+    let len = j + super::decode_rest(src, i, dst.add(j));
+
+    if is_ill_formed != 0 {
+        return Some((len, false));
+    }
+    let out = slice::from_raw_parts(dst, len);
+    let checked = naive::utf8::floor_seq_start(out, checked);
+    Some((len, str::from_utf8(&out[checked..]).is_ok()))
+}
+
+/// Checks whether every `%` in the bytes is followed by two hexadecimal digits.
+fn is_pct_encoded(s: &[u8]) -> bool {
+    s.iter().enumerate().all(|(i, &x)| {
+        x != b'%' || matches!(s[i + 1..], [hi, lo, ..] if naive::is_valid_octet(hi, lo))
+    })
+}
+
+/// Percent-encodes the bytes not allowed unencoded by the table
+/// and appends the result to `dst`.
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2,bmi2")]
+pub unsafe fn encode_into(table: impl naive::Table, src: &[u8], dst: &mut Vec<u8>) {
+    encode_into_with_case(table, src, dst, HexCase::Upper);
+}
+
+/// Percent-encodes the bytes not allowed unencoded by the table
+/// with hexadecimal digits in the given case and appends the result to `dst`.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2,bmi2")]
+pub unsafe fn encode_into_with_case(
+    table: impl naive::Table,
+    src: &[u8],
+    dst: &mut Vec<u8>,
+    case: HexCase,
+) {
+    let table = table.to_bitset();
+    const ROLE_PCT: u64 = 0x249249249249;
+
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // % is always encoded
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, (allowed.0 & !(1 << b'%')) as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let hex_digits = _mm_loadu_si128(case.digits().as_ptr().cast());
+    let hex_digits = _mm512_broadcast_i32x4(hex_digits);
+
+    // repeats each of the first 16 bytes three times
+    let triple_idx = _mm512_set_epi8(
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+        15, 15, 15, 14, 14, 14, 13, 13, 13, 12, 12, 12, 11, 11, 11, 10, //
+        10, 10, 9, 9, 9, 8, 8, 8, 7, 7, 7, 6, 6, 6, 5, 5, //
+        5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 1, 1, 1, 0, 0, 0,
+    );
+    let sixteen = _mm512_set1_epi8(16);
+
+    let mut i = 0;
+    while i + 64 <= len {
+        // the worst case plus the garbage written by the last full store
+        dst.reserve(64 * 3 + 16);
+        let out = dst.as_mut_ptr().add(dst.len());
+
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk);
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte);
+        let is_disallowed = _mm512_testn_epi8_mask(allowed_per_byte, mask_per_byte);
+
+        if is_disallowed == 0 {
+            _mm512_storeu_si512(out.cast(), chunk);
+            dst.set_len(dst.len() + 64);
             i += 64;
+            continue;
+        }
+
+        // expand 16 bytes at a time into triplets of the form (byte or %, hexdig, hexdig)
+        let mut j = 0;
+        let mut idx = triple_idx;
+        for k in 0..4 {
+            let tripled = _mm512_permutexvar_epi8(idx, chunk);
+            idx = _mm512_add_epi8(idx, sixteen);
+
+            let hi_4 = _mm512_and_si512(_mm512_srli_epi16::<4>(tripled), byte_lo_4_mask);
+            let hexdig_hi = _mm512_shuffle_epi8(hex_digits, hi_4);
+            let lo_4 = _mm512_and_si512(tripled, byte_lo_4_mask);
+            let hexdig_lo = _mm512_shuffle_epi8(hex_digits, lo_4);
+
+            let is_pct = _pdep_u64((is_disallowed >> (k * 16)) & 0xffff, ROLE_PCT);
+            let triplets = _mm512_mask_mov_epi8(tripled, is_pct, pct);
+            let triplets = _mm512_mask_mov_epi8(triplets, ROLE_PCT << 1, hexdig_hi);
+            let triplets = _mm512_mask_mov_epi8(triplets, ROLE_PCT << 2, hexdig_lo);
+
+            // keep the hexdig only after %
+            let keep = ROLE_PCT | (is_pct << 1) | (is_pct << 2);
+            let compressed = _mm512_maskz_compress_epi8(keep, triplets);
+            _mm512_storeu_si512(out.add(j).cast(), compressed);
+            j += keep.count_ones() as usize;
         }
+        dst.set_len(dst.len() + j);
+        i += 64;
+    }
+    super::encode_rest(table, src, i, dst, case);
+}
+
+/// Validates the string with the table and decodes it in a single pass.
+///
+/// This function allocates only when the string contains any percent-encoded octet.
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2")]
+pub unsafe fn decode_checked(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<Cow<'_, [u8]>, ValidateError> {
+    let table = table.to_bitset();
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = super::hexdig_after_pct(table).bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    // the lower halves of the tables suffice for hexdig
+    let octet_hi_lo = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().cast());
+    let octet_hi_hi = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().add(64).cast());
+    let octet_lo_lo = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().cast());
+    let octet_lo_hi = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().add(64).cast());
+
+    // empty until the first % is seen, after which we start decoding
+    let mut buf = Vec::new();
+    let mut decoding = false;
+
+    let mut after_pct_prev = 0u64;
+
+    let mut i = 0;
+    let mut j = 0;
+    while i + 64 + 2 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+        let after_pct = (is_pct << 1) | (is_pct << 2) | after_pct_prev;
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk);
+
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+
+        let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte);
+        let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte);
+        let table_per_byte = _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+        let is_invalid = _mm512_testn_epi8_mask(table_per_byte, mask_per_byte);
+        if is_invalid != 0 {
+            if table.allows_non_ascii() {
+                // leave the non-ASCII characters to the scalar implementation
+                break;
+            }
+            let k = i + is_invalid.trailing_zeros() as usize;
+            return Err(super::invalid_at(table, src, k));
+        }
+        after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+
+        if is_pct != 0 && !decoding {
+            // the decoded length is at most the source length
+            buf.reserve_exact(len);
+            ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), i);
+            decoding = true;
+            j = i;
+        }
+
+        if decoding {
+            let chunk_r1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
+            let chunk_r2 = _mm512_loadu_si512(ptr.add(i + 2).cast());
+
+            let octet_hi = _mm512_permutex2var_epi8(octet_hi_lo, chunk_r1, octet_hi_hi);
+            let octet_lo = _mm512_permutex2var_epi8(octet_lo_lo, chunk_r2, octet_lo_hi);
+            let octet = _mm512_or_si512(octet_hi, octet_lo);
+
+            let decoded = _mm512_mask_mov_epi8(chunk, is_pct, octet);
+            let compressed = _mm512_maskz_compress_epi8(!after_pct, decoded);
+            _mm512_storeu_si512(buf.as_mut_ptr().add(j).cast(), compressed);
+
+            j += (!after_pct).count_ones() as usize;
+        }
+        i += 64;
+    }
+
+    super::validate_rest(table, src, i)?;
+
+    if !decoding {
+        if !src[i..].contains(&b'%') {
+            return Ok(Cow::Borrowed(src));
+        }
+        buf.reserve_exact(len);
+        ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), i);
+        j = i;
+    }
+
+    i += after_pct_prev.count_ones() as usize;
+    j += super::decode_rest(src, i, buf.as_mut_ptr().add(j));
+    buf.set_len(j);
+    Ok(Cow::Owned(buf))
+}
+
+/// Normalizes the percent-encoded octets in the string as described in
+/// RFC 3986 section 6.2.2, in the same way as [`naive::normalize`].
+///
+/// This function allocates only when anything changes.
+///
+/// [`naive::normalize`]: crate::naive::normalize
+#[rustc_align(64)]
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2")]
+pub unsafe fn normalize(table: impl naive::Table, src: &[u8]) -> Cow<'_, [u8]> {
+    let table = table.to_bitset();
+    let len = src.len();
+    let ptr = src.as_ptr();
+
+    // clear the corresponding bit for %, which is never decoded
+    let allowed = table.bits();
+    let allowed = (allowed.0 & !(1 << b'%'), allowed.1);
+    let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
+    let allowed = _mm256_broadcastsi128_si256(allowed);
+    let allowed = _mm512_broadcast_i64x4(allowed);
+
+    let hexdig = table_bitset::HEXDIG.bits();
+    let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
+    let hexdig = _mm256_broadcastsi128_si256(hexdig);
+    let hexdig = _mm512_broadcast_i64x4(hexdig);
+
+    let lower_hexdig = Table::new(b"abcdef").bits();
+    let lower_hexdig = _mm_set_epi64x(lower_hexdig.1 as _, lower_hexdig.0 as _);
+    let lower_hexdig = _mm256_broadcastsi128_si256(lower_hexdig);
+    let lower_hexdig = _mm512_broadcast_i64x4(lower_hexdig);
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+    let case_diff = _mm512_set1_epi8((b'a' - b'A') as _);
+
+    // the lower halves of the tables suffice for hexdig
+    let octet_hi_lo = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().cast());
+    let octet_hi_hi = _mm512_loadu_si512(OCTET_TABLE_HI.as_ptr().add(64).cast());
+    let octet_lo_lo = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().cast());
+    let octet_lo_hi = _mm512_loadu_si512(OCTET_TABLE_LO.as_ptr().add(64).cast());
+
+    // returns the bitmask of the bytes in `x` allowed by `table`
+    let lookup = |table, x| {
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, x);
+        let word_shr_3 = _mm512_srli_epi16::<3>(x);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+        let table_per_byte = _mm512_shuffle_epi8(table, table_idx_per_byte);
+        _mm512_test_epi8_mask(table_per_byte, mask_per_byte)
+    };
+
+    // empty until anything changes, after which we start writing
+    let mut buf = Vec::new();
+    let mut writing = false;
+
+    // the bytes following a triplet, and those following a decoded one,
+    // that fall into the next block
+    let mut after_triplet_prev = 0u64;
+    let mut after_decoded_prev = 0u64;
+
+    let mut i = 0;
+    let mut j = 0;
+    while i + 64 + 2 <= len {
+        let chunk = _mm512_loadu_si512(ptr.add(i).cast());
+        let chunk_r1 = _mm512_loadu_si512(ptr.add(i + 1).cast());
+        let chunk_r2 = _mm512_loadu_si512(ptr.add(i + 2).cast());
+
+        let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+        let is_triplet = is_pct & lookup(hexdig, chunk_r1) & lookup(hexdig, chunk_r2);
+
+        let octet_hi = _mm512_permutex2var_epi8(octet_hi_lo, chunk_r1, octet_hi_hi);
+        let octet_lo = _mm512_permutex2var_epi8(octet_lo_lo, chunk_r2, octet_lo_hi);
+        let octet = _mm512_or_si512(octet_hi, octet_lo);
+
+        let is_decoded = is_triplet & lookup(allowed, octet);
+        let after_triplet = (is_triplet << 1) | (is_triplet << 2) | after_triplet_prev;
+        let after_decoded = (is_decoded << 1) | (is_decoded << 2) | after_decoded_prev;
+        let is_lowered = after_triplet & lookup(lower_hexdig, chunk);
+
+        if (is_decoded | is_lowered) != 0 && !writing {
+            // the normalized length is at most the source length
+            buf.reserve_exact(len);
+            ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), i);
+            writing = true;
+            j = i;
+        }
+
+        if writing {
+            let normalized = _mm512_mask_mov_epi8(chunk, is_decoded, octet);
+            let normalized = _mm512_mask_sub_epi8(normalized, is_lowered, normalized, case_diff);
+            let compressed = _mm512_maskz_compress_epi8(!after_decoded, normalized);
+            _mm512_storeu_si512(buf.as_mut_ptr().add(j).cast(), compressed);
+
+            j += (!after_decoded).count_ones() as usize;
+        }
+
+        after_triplet_prev = (is_triplet >> 62) | (is_triplet >> 63);
+        after_decoded_prev = (is_decoded >> 62) | (is_decoded >> 63);
+        i += 64;
+    }
+
+    // back up to the start of the straddling triplet
+    let k = match after_triplet_prev {
+        0b11 => i - 1,
+        0b01 => i - 2,
+        _ => i,
+    };
+    let rest = naive::normalize(table, &src[k..]);
+
+    if !writing {
+        let Cow::Owned(rest) = rest else {
+            return Cow::Borrowed(src);
+        };
+        buf.reserve_exact(k + rest.len());
+        buf.extend_from_slice(&src[..k]);
+        buf.extend_from_slice(&rest);
+        return Cow::Owned(buf);
+    }
+
+    // a decoded triplet has been written as a single byte
+    j -= if after_decoded_prev != 0 { 1 } else { i - k };
+    buf.set_len(j);
+    buf.extend_from_slice(&rest);
+    Cow::Owned(buf)
+}
+
+/// Scans the blocks of 64 bytes from `src[i..]` for query delimiters,
+/// as many as fit in both the string and `masks`, returning their number.
+///
+/// For each block, writes the bitmask of `&`, `;` and `=`, and, if a table
+/// is given, the bitmask of the bytes invalid against it. `after_pct_prev`
+/// carries the bitmask of the bytes following a `%` into the next block.
+#[target_feature(enable = "avx512bw")]
+pub unsafe fn scan_query(
+    src: &[u8],
+    mut i: usize,
+    table: Option<Table>,
+    after_pct_prev: &mut u64,
+    masks: &mut [(u64, u64)],
+) -> usize {
+    let broadcast = |bits: (u64, u64)| {
+        let table = _mm_set_epi64x(bits.1 as _, bits.0 as _);
+        let table = _mm256_broadcastsi128_si256(table);
+        _mm512_broadcast_i64x4(table)
+    };
+
+    let delims = broadcast(crate::query::DELIMITERS.bits());
+    // the corresponding bit for % is set in the first table iff it is allowed
+    let tables = table.map(|table| {
+        let hexdig = super::hexdig_after_pct(table);
+        (broadcast(table.bits()), broadcast(hexdig.bits()))
+    });
+
+    let pct = _mm512_set1_epi8(b'%' as _);
+    let byte_lo_4_mask = _mm512_set1_epi8(0xf);
+    let mask_table = _mm512_set1_epi64(0x8040201008040201u64 as _);
+
+    let mut n = 0;
+    while n < masks.len() && i + 64 <= src.len() {
+        let chunk = _mm512_loadu_si512(src.as_ptr().add(i).cast());
+
+        // for non-ASCII, this is 0
+        let mask_per_byte = _mm512_shuffle_epi8(mask_table, chunk);
+        let word_shr_3 = _mm512_srli_epi16::<3>(chunk);
+        let table_idx_per_byte = _mm512_and_si512(word_shr_3, byte_lo_4_mask);
+
+        let delims_per_byte = _mm512_shuffle_epi8(delims, table_idx_per_byte);
+        let is_delim = _mm512_test_epi8_mask(delims_per_byte, mask_per_byte);
+
+        let is_invalid = if let Some((allowed, hexdig)) = tables {
+            let is_pct = _mm512_cmpeq_epi8_mask(chunk, pct);
+            let after_pct = (is_pct << 1) | (is_pct << 2) | *after_pct_prev;
+            *after_pct_prev = (is_pct >> 62) | (is_pct >> 63);
+
+            let allowed_per_byte = _mm512_shuffle_epi8(allowed, table_idx_per_byte);
+            let hexdig_per_byte = _mm512_shuffle_epi8(hexdig, table_idx_per_byte);
+            let table_per_byte =
+                _mm512_mask_blend_epi8(after_pct, allowed_per_byte, hexdig_per_byte);
+
+            // this actually sets bit when AND is zero
+            _mm512_testn_epi8_mask(table_per_byte, mask_per_byte)
+        } else {
+            0
+        };
+
+        masks[n] = (is_delim, is_invalid);
+        n += 1;
+        i += 64;
     }
-    table_bitset::PATH.validate(&src[i..])
+    n
 }
@@ -0,0 +1,670 @@
+//! Runtime CPU feature detection and dispatch.
+
+use crate::naive::{self, Encode, HexCase, table_bitset::Table};
+use crate::{ValidateError, avx2, avx512, sse41, ssse3};
+use std::{
+    borrow::Cow,
+    env, fmt, mem, str,
+    string::FromUtf8Error,
+    sync::atomic::{AtomicPtr, AtomicU8, Ordering},
+    thread,
+};
+
+/// The environment variable for forcing a specific backend, for example
+/// `PCT_ENC_BACKEND=avx2`.
+///
+/// An unknown backend, or one not supported by the current CPU, is ignored.
+pub const BACKEND_ENV_VAR: &str = "PCT_ENC_BACKEND";
+
+type ValidateFn = unsafe fn(Table, &[u8]) -> bool;
+type ValidateDetailedFn = unsafe fn(Table, &[u8]) -> Result<(), ValidateError>;
+type EncodeIntoFn = unsafe fn(Table, &[u8], &mut Vec<u8>, HexCase);
+type ValidateManyFn = unsafe fn(Table, &[&[u8]]) -> Vec<bool>;
+type DecodeCheckedFn = unsafe fn(Table, &[u8]) -> Result<Cow<'_, [u8]>, ValidateError>;
+type NormalizeFn = unsafe fn(Table, &[u8]) -> Cow<'_, [u8]>;
+type DecodeInPlaceFn = unsafe fn(&mut [u8]) -> usize;
+type DecodeUtf8Fn = unsafe fn(&[u8]) -> (Cow<'_, [u8]>, bool);
+
+/// An implementation of the validator.
+///
+/// A backend also selects the implementations of the other functions
+/// in this module, which fall back to the scalar ones for backends
+/// without their own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// [`Table::validate`], available everywhere.
+    Naive,
+    /// [`ssse3::validate_3load`].
+    Ssse3,
+    /// [`sse41::validate_alignr`].
+    Sse41,
+    /// [`avx2::validate_3load`].
+    Avx2,
+    /// [`avx512::validate_3load`].
+    Avx512,
+    /// [`avx512::validate_3load_gf2p8affine`].
+    Avx512Gfni,
+    /// [`avx512::validate_3load_perm`], or [`avx512::validate_iri`]
+    /// for tables that allow non-ASCII characters.
+    Avx512Vbmi,
+}
+
+impl Backend {
+    /// All backends, from the slowest to the fastest.
+    pub const ALL: [Self; 7] = [
+        Self::Naive,
+        Self::Ssse3,
+        Self::Sse41,
+        Self::Avx2,
+        Self::Avx512,
+        Self::Avx512Gfni,
+        Self::Avx512Vbmi,
+    ];
+
+    /// Returns the name of the backend, as accepted by [`Backend::from_name`].
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Naive => "naive",
+            Self::Ssse3 => "ssse3",
+            Self::Sse41 => "sse41",
+            Self::Avx2 => "avx2",
+            Self::Avx512 => "avx512",
+            Self::Avx512Gfni => "avx512-gfni",
+            Self::Avx512Vbmi => "avx512-vbmi",
+        }
+    }
+
+    /// Looks up a backend by its name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|b| b.name() == name)
+    }
+
+    /// Checks whether the backend is supported by the current CPU.
+    #[must_use]
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Naive => true,
+            Self::Ssse3 => is_x86_feature_detected!("ssse3"),
+            Self::Sse41 => is_x86_feature_detected!("sse4.1"),
+            Self::Avx2 => is_x86_feature_detected!("avx2"),
+            Self::Avx512 => is_x86_feature_detected!("avx512bw"),
+            Self::Avx512Gfni => {
+                is_x86_feature_detected!("avx512bw") && is_x86_feature_detected!("gfni")
+            }
+            Self::Avx512Vbmi => {
+                is_x86_feature_detected!("avx512bw") && is_x86_feature_detected!("avx512vbmi")
+            }
+        }
+    }
+
+    /// Returns the fastest backend supported by the current CPU.
+    #[must_use]
+    pub fn detect() -> Self {
+        Self::ALL
+            .into_iter()
+            .rfind(|b| b.is_supported())
+            .unwrap_or(Self::Naive)
+    }
+
+    fn validate_fn(self) -> ValidateFn {
+        match self {
+            Self::Naive => |table, src| table.validate(src),
+            Self::Ssse3 => ssse3::validate_3load,
+            Self::Sse41 => sse41::validate_alignr,
+            Self::Avx2 => avx2::validate_3load,
+            Self::Avx512 => avx512::validate_3load,
+            Self::Avx512Gfni => avx512::validate_3load_gf2p8affine,
+            Self::Avx512Vbmi => |table, src| unsafe {
+                if table.allows_non_ascii() {
+                    avx512::validate_iri(table, src)
+                } else {
+                    avx512::validate_3load_perm(table, src)
+                }
+            },
+        }
+    }
+
+    fn validate_detailed_fn(self) -> ValidateDetailedFn {
+        match self {
+            Self::Naive => |table, src| table.validate_detailed(src),
+            Self::Ssse3 => ssse3::validate_3load_detailed,
+            Self::Sse41 => sse41::validate_alignr_detailed,
+            Self::Avx2 => avx2::validate_3load_detailed,
+            Self::Avx512 => avx512::validate_3load_detailed,
+            Self::Avx512Gfni => avx512::validate_3load_gf2p8affine_detailed,
+            Self::Avx512Vbmi => |table, src| unsafe {
+                if table.allows_non_ascii() {
+                    avx512::validate_iri_detailed(table, src)
+                } else {
+                    avx512::validate_3load_perm_detailed(table, src)
+                }
+            },
+        }
+    }
+
+    /// Checks whether the AVX-512 routines requiring VBMI2 are used with this backend.
+    fn uses_vbmi2(self) -> bool {
+        self == Self::Avx512Vbmi && is_x86_feature_detected!("avx512vbmi2")
+    }
+
+    fn encode_into_fn(self) -> EncodeIntoFn {
+        let avx512 = self.uses_vbmi2() && is_x86_feature_detected!("bmi2");
+        let avx2 = matches!(
+            self,
+            Self::Avx2 | Self::Avx512 | Self::Avx512Gfni | Self::Avx512Vbmi
+        ) && is_x86_feature_detected!("avx2")
+            && is_x86_feature_detected!("popcnt");
+
+        if avx512 {
+            avx512::encode_into_with_case
+        } else if avx2 {
+            avx2::encode_into_with_case
+        } else {
+            |table, src, dst, case| {
+                for chunk in Encode::new(table, src).hex_case(case) {
+                    dst.extend_from_slice(chunk.as_bytes());
+                }
+            }
+        }
+    }
+
+    fn validate_many_fn(self) -> ValidateManyFn {
+        if self.uses_vbmi2() {
+            |table, srcs| unsafe { avx512::validate_many(table, srcs) }
+        } else {
+            // `validate` is dispatched to this backend along with this function
+            |table, srcs| srcs.iter().map(|src| validate(table, src)).collect()
+        }
+    }
+
+    fn decode_checked_fn(self) -> DecodeCheckedFn {
+        if self.uses_vbmi2() {
+            avx512::decode_checked
+        } else {
+            |table, src| naive::decode_checked(table, src)
+        }
+    }
+
+    fn normalize_fn(self) -> NormalizeFn {
+        if self.uses_vbmi2() {
+            avx512::normalize
+        } else {
+            |table, src| naive::normalize(table, src)
+        }
+    }
+
+    fn decode_in_place_fn(self) -> DecodeInPlaceFn {
+        if self.uses_vbmi2() {
+            avx512::decode_in_place
+        } else {
+            |buf| naive::decode_in_place(buf)
+        }
+    }
+
+    fn decode_utf8_fn(self) -> DecodeUtf8Fn {
+        if self.uses_vbmi2() {
+            decode_utf8_avx512
+        } else {
+            decode_utf8_naive
+        }
+    }
+
+    /// Validates the given string with the table, using this backend.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    pub fn validate(self, table: impl naive::Table, src: &[u8]) -> bool {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        unsafe { self.validate_fn()(table.to_bitset(), src) }
+    }
+
+    /// Validates the given string with the table, using this backend,
+    /// returning the offset and kind of the first error on failure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    pub fn validate_detailed(
+        self,
+        table: impl naive::Table,
+        src: &[u8],
+    ) -> Result<(), ValidateError> {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        unsafe { self.validate_detailed_fn()(table.to_bitset(), src) }
+    }
+
+    /// Percent-encodes the bytes not allowed unencoded by the table
+    /// and appends the result to `dst`, using this backend.
+    ///
+    /// Backends without an encoder of their own fall back to [`Encode`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    pub fn encode_into(self, table: impl naive::Table, src: &[u8], dst: &mut Vec<u8>) {
+        self.encode_into_with_case(table, src, dst, HexCase::Upper);
+    }
+
+    /// Percent-encodes the bytes not allowed unencoded by the table
+    /// with hexadecimal digits in the given case and appends the result
+    /// to `dst`, using this backend.
+    ///
+    /// Backends without an encoder of their own fall back to [`Encode`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    pub fn encode_into_with_case(
+        self,
+        table: impl naive::Table,
+        src: &[u8],
+        dst: &mut Vec<u8>,
+        case: HexCase,
+    ) {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        unsafe { self.encode_into_fn()(table.to_bitset(), src, dst, case) }
+    }
+
+    /// Validates each of the strings with the table like [`validate_many`],
+    /// using this backend.
+    ///
+    /// Backends other than [`Backend::Avx512Vbmi`], and that one on a CPU
+    /// without AVX-512 VBMI2, validate the strings one at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    #[must_use]
+    pub fn validate_many(self, table: impl naive::Table, srcs: &[&[u8]]) -> Vec<bool> {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        let table = table.to_bitset();
+        if self.uses_vbmi2() {
+            unsafe { avx512::validate_many(table, srcs) }
+        } else {
+            let f = self.validate_fn();
+            srcs.iter().map(|src| unsafe { f(table, src) }).collect()
+        }
+    }
+
+    /// Validates the string with the table and decodes it, using this backend.
+    ///
+    /// Backends other than [`Backend::Avx512Vbmi`], and that one on a CPU
+    /// without AVX-512 VBMI2, fall back to [`naive::decode_checked`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    pub fn decode_checked(
+        self,
+        table: impl naive::Table,
+        src: &[u8],
+    ) -> Result<Cow<'_, [u8]>, ValidateError> {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        unsafe { self.decode_checked_fn()(table.to_bitset(), src) }
+    }
+
+    /// Normalizes the percent-encoded octets in the string like [`normalize`],
+    /// using this backend.
+    ///
+    /// Backends other than [`Backend::Avx512Vbmi`], and that one on a CPU
+    /// without AVX-512 VBMI2, fall back to [`naive::normalize`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    #[must_use]
+    pub fn normalize(self, table: impl naive::Table, src: &[u8]) -> Cow<'_, [u8]> {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        unsafe { self.normalize_fn()(table.to_bitset(), src) }
+    }
+
+    /// Decodes the percent-encoded octets in the buffer in place like
+    /// [`decode_in_place`], using this backend.
+    ///
+    /// Backends other than [`Backend::Avx512Vbmi`], and that one on a CPU
+    /// without AVX-512 VBMI2, fall back to [`naive::decode_in_place`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not supported by the current CPU.
+    pub fn decode_in_place(self, buf: &mut [u8]) -> usize {
+        assert!(self.is_supported(), "unsupported backend: {self}");
+        unsafe { self.decode_in_place_fn()(buf) }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The index of the selected backend in [`Backend::ALL`], or `u8::MAX` if none is selected yet.
+static BACKEND: AtomicU8 = AtomicU8::new(u8::MAX);
+static VALIDATE_FN: AtomicPtr<()> = AtomicPtr::new(init_and_validate as *mut ());
+static VALIDATE_DETAILED_FN: AtomicPtr<()> = AtomicPtr::new(init_and_validate_detailed as *mut ());
+static ENCODE_INTO_FN: AtomicPtr<()> = AtomicPtr::new(init_and_encode_into as *mut ());
+static VALIDATE_MANY_FN: AtomicPtr<()> = AtomicPtr::new(init_and_validate_many as *mut ());
+static DECODE_CHECKED_FN: AtomicPtr<()> = AtomicPtr::new(init_and_decode_checked as *mut ());
+static NORMALIZE_FN: AtomicPtr<()> = AtomicPtr::new(init_and_normalize as *mut ());
+static DECODE_IN_PLACE_FN: AtomicPtr<()> = AtomicPtr::new(init_and_decode_in_place as *mut ());
+static DECODE_UTF8_FN: AtomicPtr<()> = AtomicPtr::new(init_and_decode_utf8 as *mut ());
+
+fn init() {
+    let backend = env::var(BACKEND_ENV_VAR)
+        .ok()
+        .and_then(|name| Backend::from_name(&name))
+        .filter(|b| b.is_supported())
+        .unwrap_or_else(Backend::detect);
+    set_backend(backend);
+}
+
+unsafe fn init_and_validate(table: Table, src: &[u8]) -> bool {
+    init();
+    validate(table, src)
+}
+
+unsafe fn init_and_validate_detailed(table: Table, src: &[u8]) -> Result<(), ValidateError> {
+    init();
+    validate_detailed(table, src)
+}
+
+unsafe fn init_and_encode_into(table: Table, src: &[u8], dst: &mut Vec<u8>, case: HexCase) {
+    init();
+    encode_into_with_case(table, src, dst, case);
+}
+
+unsafe fn init_and_validate_many(table: Table, srcs: &[&[u8]]) -> Vec<bool> {
+    init();
+    validate_many(table, srcs)
+}
+
+unsafe fn init_and_decode_checked(
+    table: Table,
+    src: &[u8],
+) -> Result<Cow<'_, [u8]>, ValidateError> {
+    init();
+    decode_checked(table, src)
+}
+
+unsafe fn init_and_normalize(table: Table, src: &[u8]) -> Cow<'_, [u8]> {
+    init();
+    normalize(table, src)
+}
+
+unsafe fn init_and_decode_in_place(buf: &mut [u8]) -> usize {
+    init();
+    decode_in_place(buf)
+}
+
+unsafe fn init_and_decode_utf8(src: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    init();
+    decode_utf8(src)
+}
+
+/// Returns the backend used by the functions in this module.
+///
+/// On first call, this selects the backend in the same way as [`validate`].
+#[must_use]
+pub fn backend() -> Backend {
+    match BACKEND.load(Ordering::Relaxed) {
+        u8::MAX => {
+            init();
+            backend()
+        }
+        i => Backend::ALL[i as usize],
+    }
+}
+
+/// Forces all subsequent calls to the functions in this module,
+/// and the query splitters in [`query`](crate::query), to use the given backend.
+///
+/// # Panics
+///
+/// Panics if the backend is not supported by the current CPU.
+pub fn set_backend(backend: Backend) {
+    assert!(backend.is_supported(), "unsupported backend: {backend}");
+    // the backends are declared in the same order as in `Backend::ALL`
+    BACKEND.store(backend as u8, Ordering::Relaxed);
+    VALIDATE_FN.store(backend.validate_fn() as *mut (), Ordering::Relaxed);
+    VALIDATE_DETAILED_FN.store(backend.validate_detailed_fn() as *mut (), Ordering::Relaxed);
+    ENCODE_INTO_FN.store(backend.encode_into_fn() as *mut (), Ordering::Relaxed);
+    VALIDATE_MANY_FN.store(backend.validate_many_fn() as *mut (), Ordering::Relaxed);
+    DECODE_CHECKED_FN.store(backend.decode_checked_fn() as *mut (), Ordering::Relaxed);
+    NORMALIZE_FN.store(backend.normalize_fn() as *mut (), Ordering::Relaxed);
+    DECODE_IN_PLACE_FN.store(backend.decode_in_place_fn() as *mut (), Ordering::Relaxed);
+    DECODE_UTF8_FN.store(backend.decode_utf8_fn() as *mut (), Ordering::Relaxed);
+}
+
+/// Validates the given string with the table.
+///
+/// On first call, this detects the fastest backend supported by the current CPU,
+/// unless one is specified by the environment variable [`BACKEND_ENV_VAR`].
+/// Use [`set_backend`] to force a specific backend.
+pub fn validate(table: impl naive::Table, src: &[u8]) -> bool {
+    let f = VALIDATE_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), ValidateFn>(f);
+        f(table.to_bitset(), src)
+    }
+}
+
+/// Validates the given string with the table, returning the offset
+/// and kind of the first error on failure.
+///
+/// The backend is chosen in the same way as [`validate`].
+pub fn validate_detailed(table: impl naive::Table, src: &[u8]) -> Result<(), ValidateError> {
+    let f = VALIDATE_DETAILED_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), ValidateDetailedFn>(f);
+        f(table.to_bitset(), src)
+    }
+}
+
+/// Percent-encodes the bytes not allowed unencoded by the table
+/// and appends the result to `dst`.
+///
+/// The backend is chosen in the same way as [`validate`].
+/// Backends without an encoder of their own fall back to [`Encode`].
+pub fn encode_into(table: impl naive::Table, src: &[u8], dst: &mut Vec<u8>) {
+    encode_into_with_case(table, src, dst, HexCase::Upper);
+}
+
+/// Percent-encodes the bytes not allowed unencoded by the table
+/// with hexadecimal digits in the given case and appends the result to `dst`.
+///
+/// The backend is chosen in the same way as [`validate`].
+/// Backends without an encoder of their own fall back to [`Encode`].
+pub fn encode_into_with_case(
+    table: impl naive::Table,
+    src: &[u8],
+    dst: &mut Vec<u8>,
+    case: HexCase,
+) {
+    let f = ENCODE_INTO_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), EncodeIntoFn>(f);
+        f(table.to_bitset(), src, dst, case);
+    }
+}
+
+/// Validates the given string with the table like [`validate_detailed`],
+/// splitting it into chunks that are validated on up to `threads` threads.
+///
+/// The chunks are a multiple of 64 bytes long, except that a boundary is moved
+/// back to the start of any UTF-8 sequence it would split. A triplet straddling
+/// a boundary is validated by both chunks around it.
+/// On failure, the error is the same as that returned by [`validate_detailed`].
+///
+/// # Panics
+///
+/// Panics if `threads` is zero.
+pub fn par_validate(
+    table: impl naive::Table,
+    src: &[u8],
+    threads: usize,
+) -> Result<(), ValidateError> {
+    assert!(threads != 0, "threads must be nonzero");
+    let table = table.to_bitset();
+    let chunk_len = src.len().div_ceil(threads).next_multiple_of(64).max(64);
+
+    let splits = (chunk_len..src.len())
+        .step_by(chunk_len)
+        .map(|i| naive::utf8::floor_seq_start(src, i));
+    let bounds: Vec<usize> = [0].into_iter().chain(splits).chain([src.len()]).collect();
+
+    thread::scope(|s| {
+        let workers: Vec<_> = bounds[1..]
+            .windows(2)
+            .map(|w| s.spawn(move || validate_chunk(table, src, w[0], w[1])))
+            .collect();
+
+        // an error in a chunk precedes any in the chunks after it
+        validate_chunk(table, src, bounds[0], bounds[1])?;
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().unwrap())
+    })
+}
+
+/// Validates `src[start..end]`, given that neither index splits a UTF-8 sequence.
+///
+/// The error returned, if any, is the first one in `src`
+/// unless there is an earlier one in `src[..start]`.
+fn validate_chunk(table: Table, src: &[u8], start: usize, end: usize) -> Result<(), ValidateError> {
+    // back up to the `%` of a triplet straddling `start`
+    let from = match src[..start] {
+        [.., b'%'] => start - 1,
+        [.., b'%', _] => start - 2,
+        _ => start,
+    };
+    // include the rest of a triplet straddling `end`
+    let to = src.len().min(end + 2);
+
+    match validate_detailed(table, &src[from..to]) {
+        Err(e) if from + e.offset() < end => Err(e.shift(from)),
+        _ => Ok(()),
+    }
+}
+
+/// Validates each of the strings with the table, returning whether each is valid.
+///
+/// The backend is chosen in the same way as [`validate`].
+/// With [`Backend::Avx512Vbmi`] on a CPU supporting AVX-512 VBMI2,
+/// short strings are validated several at a time, which is much faster
+/// than calling [`validate`] on each of them.
+#[must_use]
+pub fn validate_many(table: impl naive::Table, srcs: &[&[u8]]) -> Vec<bool> {
+    let f = VALIDATE_MANY_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), ValidateManyFn>(f);
+        f(table.to_bitset(), srcs)
+    }
+}
+
+/// Validates the string with the table and decodes it,
+/// in a single pass with the [`Backend::Avx512Vbmi`] backend.
+///
+/// The backend is chosen in the same way as [`validate`].
+/// This function allocates only when the string contains any percent-encoded octet.
+pub fn decode_checked(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<Cow<'_, [u8]>, ValidateError> {
+    let f = DECODE_CHECKED_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), DecodeCheckedFn>(f);
+        f(table.to_bitset(), src)
+    }
+}
+
+/// Decodes the percent-encoded octets in the buffer in place,
+/// using SIMD with the [`Backend::Avx512Vbmi`] backend.
+///
+/// The backend is chosen in the same way as [`validate`].
+/// See [`naive::decode_in_place`] for details.
+pub fn decode_in_place(buf: &mut [u8]) -> usize {
+    let f = DECODE_IN_PLACE_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), DecodeInPlaceFn>(f);
+        f(buf)
+    }
+}
+
+/// Decodes the percent-encoded octets in the vector in place like
+/// [`decode_in_place`], truncating it to the decoded length.
+pub fn decode_vec_in_place(buf: &mut Vec<u8>) {
+    let len = decode_in_place(buf);
+    buf.truncate(len);
+}
+
+/// Decodes the percent-encoded octets in the string in place like
+/// [`decode_in_place`], checking that the result is valid UTF-8.
+///
+/// On failure, the decoded bytes are returned in the error.
+pub fn decode_string_in_place(s: String) -> Result<String, FromUtf8Error> {
+    let mut buf = s.into_bytes();
+    decode_vec_in_place(&mut buf);
+    String::from_utf8(buf)
+}
+
+/// Normalizes the percent-encoded octets in the string as described in
+/// RFC 3986 section 6.2.2, using SIMD with the [`Backend::Avx512Vbmi`] backend.
+///
+/// The backend is chosen in the same way as [`validate`].
+/// See [`naive::normalize`] for details.
+///
+/// Every octet allowed unencoded by the table is decoded, delimiters included.
+/// With a table that allows `/`, such as [`PATH`], `%2F` becomes `/`, and likewise
+/// `%3F` becomes `?` with [`QUERY`], which changes the meaning of a URL.
+/// Use a table that allows only unreserved characters, such as [`UNRESERVED`],
+/// where the result must identify the same resource, for example in cache keys.
+///
+/// [`PATH`]: naive::table_bitset::PATH
+/// [`QUERY`]: naive::table_bitset::QUERY
+/// [`UNRESERVED`]: naive::table_bitset::UNRESERVED
+#[must_use]
+pub fn normalize(table: impl naive::Table, src: &[u8]) -> Cow<'_, [u8]> {
+    let f = NORMALIZE_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), NormalizeFn>(f);
+        f(table.to_bitset(), src)
+    }
+}
+
+/// Decodes the string, checking in the same pass whether the result is
+/// valid UTF-8, using SIMD with the [`Backend::Avx512Vbmi`] backend.
+///
+/// A string that is not properly percent-encoded is decoded as by [`naive::Decode`].
+/// This function allocates only when the string contains any `%`.
+pub(crate) fn decode_utf8(src: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    let f = DECODE_UTF8_FN.load(Ordering::Relaxed);
+    unsafe {
+        let f = mem::transmute::<*mut (), DecodeUtf8Fn>(f);
+        f(src)
+    }
+}
+
+fn decode_utf8_naive(src: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    let buf = naive::Decode::new(src).to_bytes();
+    let valid = str::from_utf8(&buf).is_ok();
+    (buf, valid)
+}
+
+#[target_feature(enable = "avx512bw,avx512vbmi,avx512vbmi2")]
+unsafe fn decode_utf8_avx512(src: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    if !src.contains(&b'%') {
+        return (Cow::Borrowed(src), avx512::validate_utf8(src));
+    }
+
+    let mut buf = Vec::with_capacity(src.len());
+    if let Some((len, valid)) = avx512::decode_utf8(src, buf.as_mut_ptr()) {
+        buf.set_len(len);
+        (Cow::Owned(buf), valid)
+    } else {
+        decode_utf8_naive(src)
+    }
+}
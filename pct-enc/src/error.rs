@@ -0,0 +1,81 @@
+//! Error types.
+
+use std::{error::Error, fmt};
+
+/// An error occurred when validating a string with a table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidateError {
+    offset: usize,
+    kind: ValidateErrorKind,
+}
+
+/// The kind of a [`ValidateError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidateErrorKind {
+    /// An ASCII byte not allowed by the table, including a `%`
+    /// when percent-encoded octets are not allowed.
+    DisallowedByte,
+    /// A non-ASCII byte not following a `%`, when the table
+    /// does not allow any non-ASCII character.
+    NonAsciiByte,
+    /// A non-ASCII byte starting an ill-formed UTF-8 sequence,
+    /// when the table allows some non-ASCII characters.
+    InvalidUtf8,
+    /// A well-formed UTF-8 sequence encoding a code point
+    /// not allowed by the table.
+    DisallowedCodePoint,
+    /// A `%` not followed by two more bytes.
+    TruncatedTriplet,
+    /// A byte following a `%` that is not a hexadecimal digit.
+    NonHexDigit,
+}
+
+impl ValidateError {
+    pub(crate) const fn new(offset: usize, kind: ValidateErrorKind) -> Self {
+        Self { offset, kind }
+    }
+
+    /// Creates an error for a byte that is not allowed unencoded.
+    pub(crate) const fn disallowed(offset: usize, x: u8) -> Self {
+        let kind = if x.is_ascii() {
+            ValidateErrorKind::DisallowedByte
+        } else {
+            ValidateErrorKind::NonAsciiByte
+        };
+        Self { offset, kind }
+    }
+
+    pub(crate) const fn shift(self, n: usize) -> Self {
+        Self::new(self.offset + n, self.kind)
+    }
+
+    /// Returns the byte offset at which the error occurred.
+    ///
+    /// For [`ValidateErrorKind::TruncatedTriplet`], this is the offset of the `%`.
+    #[must_use]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the kind of the error.
+    #[must_use]
+    pub const fn kind(&self) -> ValidateErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for ValidateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self.kind {
+            ValidateErrorKind::DisallowedByte => "disallowed byte",
+            ValidateErrorKind::NonAsciiByte => "non-ASCII byte",
+            ValidateErrorKind::InvalidUtf8 => "invalid UTF-8 sequence",
+            ValidateErrorKind::DisallowedCodePoint => "disallowed code point",
+            ValidateErrorKind::TruncatedTriplet => "truncated percent-encoded octet",
+            ValidateErrorKind::NonHexDigit => "non-hexadecimal digit after %",
+        };
+        write!(f, "{msg} at offset {}", self.offset)
+    }
+}
+
+impl Error for ValidateError {}
@@ -2,18 +2,139 @@
 
 pub mod avx2;
 pub mod avx512;
+pub mod dispatch;
+pub mod error;
 pub mod naive;
+pub mod query;
 pub mod sse41;
 pub mod ssse3;
+pub mod stream;
 
-use crate::naive::table_bitset;
+pub use dispatch::{
+    decode_checked, decode_in_place, decode_string_in_place, decode_vec_in_place, encode_into,
+    encode_into_with_case, normalize, par_validate, validate, validate_detailed, validate_many,
+};
+pub use error::{ValidateError, ValidateErrorKind};
+
+use crate::naive::{
+    Decode, DecodedChunk, Encode, HexCase,
+    table_bitset::{self, Table},
+};
+use std::ptr;
 
 #[inline(always)]
-fn validate_first_two(src: &[u8]) -> bool {
+fn first_two_valid(table: Table, src: &[u8]) -> bool {
     let (a, b) = (src[0], src[1]);
     if a == b'%' {
-        table_bitset::HEXDIG.allows_ascii(b)
+        table.allows_pct_encoded() && table_bitset::HEXDIG.allows_ascii(b)
+    } else {
+        table.allows_ascii(a) && table.allows_ascii_with_pct(b)
+    }
+}
+
+#[cold]
+fn fail_in_first_two(table: Table, src: &[u8]) -> Result<(), ValidateError> {
+    if src[0] == b'%' || !table.allows_ascii(src[0]) {
+        fail_at(table, src, 0)
+    } else {
+        fail_at(table, src, 1)
+    }
+}
+
+/// Handles a failure found by an ASCII-only kernel at index `i`,
+/// given that `src[..i]` is valid.
+///
+/// If the table allows any non-ASCII character, the failure may be a false one,
+/// so the rest of the string is validated with the scalar implementation.
+#[cold]
+fn fail_at(table: Table, src: &[u8], i: usize) -> Result<(), ValidateError> {
+    if table.allows_non_ascii() {
+        validate_rest(table, src, i)
+    } else {
+        Err(invalid_at(table, src, i))
+    }
+}
+
+/// Returns the error at index `i`, given that `src[..i]` is valid
+/// and that either `src[i]` is invalid or it is a `%` followed by
+/// invalid bytes.
+#[cold]
+fn invalid_at(table: Table, src: &[u8], i: usize) -> ValidateError {
+    let x = src[i];
+    if let [.., b'%'] | [.., b'%', _] = src[..i] {
+        ValidateError::new(i, ValidateErrorKind::NonHexDigit)
+    } else if x == b'%' && table.allows_pct_encoded() {
+        match src[i + 1..] {
+            [hi, ..] if !hi.is_ascii_hexdigit() => {
+                ValidateError::new(i + 1, ValidateErrorKind::NonHexDigit)
+            }
+            [_, lo, ..] if !lo.is_ascii_hexdigit() => {
+                ValidateError::new(i + 2, ValidateErrorKind::NonHexDigit)
+            }
+            _ => ValidateError::new(i, ValidateErrorKind::TruncatedTriplet),
+        }
+    } else {
+        ValidateError::disallowed(i, x)
+    }
+}
+
+/// Returns the table to check the two bytes following a `%` with,
+/// which allows nothing if percent-encoded octets are disallowed.
+#[inline(always)]
+const fn hexdig_after_pct(table: Table) -> Table {
+    if table.allows_pct_encoded() {
+        table_bitset::HEXDIG
     } else {
-        table_bitset::PATH.allows_ascii_with_pct(a) || table_bitset::PATH.allows_ascii_with_pct(b)
+        Table::new(&[])
+    }
+}
+
+/// Returns a bitmask of the bytes starting at `src[i]` that follow a `%`
+/// within two bytes, given a bitmask of the bytes that equal `%`.
+#[inline(always)]
+fn after_pct_mask(src: &[u8], i: usize, is_pct: u64) -> u64 {
+    let pct_before = |k| i >= k && src[i - k] == b'%';
+    (is_pct << 1) | (is_pct << 2) | (pct_before(1) as u64 * 0b11) | pct_before(2) as u64
+}
+
+/// Validates the rest of the string from index `i` with the table,
+/// given that `src[..i]` is valid except possibly for a percent-encoded
+/// octet or a UTF-8 sequence straddling index `i`.
+#[inline(always)]
+fn validate_rest(table: Table, src: &[u8], i: usize) -> Result<(), ValidateError> {
+    // back up to the start of the straddling octet or sequence
+    let i = match src[..i] {
+        [.., b'%'] => i - 1,
+        [.., b'%', _] => i - 2,
+        _ => naive::utf8::floor_seq_start(src, i),
+    };
+    table.validate_detailed(&src[i..]).map_err(|e| e.shift(i))
+}
+
+/// Decodes the rest of the string from index `i` into `dst`,
+/// returning the number of bytes written.
+#[inline(always)]
+unsafe fn decode_rest(src: &[u8], i: usize, dst: *mut u8) -> usize {
+    let mut len = 0;
+    for chunk in Decode::new(&src[i..]) {
+        match chunk {
+            DecodedChunk::Unencoded(s) => {
+                ptr::copy_nonoverlapping(s.as_ptr(), dst.add(len), s.len());
+                len += s.len();
+            }
+            DecodedChunk::PctDecoded(x) => {
+                *dst.add(len) = x;
+                len += 1;
+            }
+        }
+    }
+    len
+}
+
+/// Percent-encodes the rest of the string from index `i` into `dst`.
+#[inline(always)]
+fn encode_rest(table: Table, src: &[u8], i: usize, dst: &mut Vec<u8>, case: HexCase) {
+    for chunk in Encode::new(table, &src[i..]).hex_case(case) {
+        dst.extend_from_slice(chunk.as_bytes());
     }
 }
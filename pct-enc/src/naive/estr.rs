@@ -0,0 +1,241 @@
+//! Percent-encoded string types.
+
+use super::{
+    Decode,
+    table_bitset::{RESERVED, Table},
+};
+use crate::ValidateError;
+use std::{borrow::Borrow, iter::FusedIterator, ops::Deref};
+
+/// Percent-encoded byte string slices.
+///
+/// An `EStr` can only be obtained through a successful validation against a table,
+/// so every `%` in it is followed by two hexadecimal digits.
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct EStr {
+    inner: [u8],
+}
+
+impl EStr {
+    /// An empty `EStr` slice.
+    pub const EMPTY: &'static EStr = EStr::new_validated(b"");
+
+    /// Validates the given bytes with the table and converts them into an `EStr` slice.
+    pub fn new(table: impl super::Table, s: &[u8]) -> Result<&EStr, ValidateError> {
+        crate::validate_detailed(table, s)?;
+        Ok(EStr::new_validated(s))
+    }
+
+    /// Converts bytes known to be valid against some table into an `EStr` slice.
+    pub(crate) const fn new_validated(s: &[u8]) -> &EStr {
+        // SAFETY: `EStr` is a transparent wrapper around `[u8]`.
+        unsafe { &*(s as *const [u8] as *const EStr) }
+    }
+
+    /// Returns the underlying bytes.
+    #[must_use]
+    pub const fn as_bytes(&self) -> &[u8] {
+        &self.inner
+    }
+
+    /// Returns the length of the `EStr` slice in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Checks whether the `EStr` slice is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns an iterator used to decode the `EStr` slice.
+    pub fn decode(&self) -> Decode<'_> {
+        Decode::new(&self.inner)
+    }
+
+    /// Returns an iterator over subslices of the `EStr` slice separated by the delimiter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delimiter is not a reserved character.
+    pub fn split(&self, delim: u8) -> Split<'_> {
+        assert!(
+            RESERVED.allows_ascii(delim),
+            "splitting with non-reserved character"
+        );
+        Split {
+            inner: &self.inner,
+            delim,
+            finished: false,
+        }
+    }
+
+    /// Splits the `EStr` slice on the first occurrence of the delimiter and
+    /// returns the prefix before it and the suffix after it.
+    ///
+    /// Returns `None` if the delimiter is not found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delimiter is not a reserved character.
+    #[must_use]
+    pub fn split_once(&self, delim: u8) -> Option<(&EStr, &EStr)> {
+        assert!(
+            RESERVED.allows_ascii(delim),
+            "splitting with non-reserved character"
+        );
+        let i = self.inner.iter().position(|&x| x == delim)?;
+        Some((
+            EStr::new_validated(&self.inner[..i]),
+            EStr::new_validated(&self.inner[i + 1..]),
+        ))
+    }
+}
+
+impl AsRef<[u8]> for EStr {
+    fn as_ref(&self) -> &[u8] {
+        &self.inner
+    }
+}
+
+impl Default for &EStr {
+    fn default() -> Self {
+        EStr::EMPTY
+    }
+}
+
+/// An iterator over subslices of an [`EStr`] slice separated by a delimiter.
+///
+/// This struct is created by [`EStr::split`].
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct Split<'a> {
+    inner: &'a [u8],
+    delim: u8,
+    finished: bool,
+}
+
+impl<'a> Iterator for Split<'a> {
+    type Item = &'a EStr;
+
+    fn next(&mut self) -> Option<&'a EStr> {
+        if self.finished {
+            return None;
+        }
+
+        let head = match self.inner.iter().position(|&x| x == self.delim) {
+            Some(i) => {
+                let head = &self.inner[..i];
+                self.inner = &self.inner[i + 1..];
+                head
+            }
+            None => {
+                self.finished = true;
+                self.inner
+            }
+        };
+        // splitting at a reserved character never cuts a triplet or a UTF-8 sequence
+        Some(EStr::new_validated(head))
+    }
+}
+
+impl FusedIterator for Split<'_> {}
+
+/// A percent-encoded, growable byte string.
+///
+/// An `EString` remembers the table it is valid against, and only grows
+/// with bytes that keep it valid.
+#[derive(Clone, Debug)]
+pub struct EString {
+    buf: Vec<u8>,
+    table: Table,
+}
+
+impl EString {
+    /// Creates a new empty `EString` valid against the table.
+    #[must_use]
+    pub fn new(table: impl super::Table) -> Self {
+        Self::with_capacity(table, 0)
+    }
+
+    /// Creates a new empty `EString` valid against the table,
+    /// with at least the specified capacity.
+    #[must_use]
+    pub fn with_capacity(table: impl super::Table, capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+            table: table.to_bitset(),
+        }
+    }
+
+    /// Returns the table the `EString` is valid against.
+    #[must_use]
+    pub fn table(&self) -> Table {
+        self.table
+    }
+
+    /// Coerces to an `EStr` slice.
+    #[must_use]
+    pub fn as_estr(&self) -> &EStr {
+        EStr::new_validated(&self.buf)
+    }
+
+    /// Encodes the given bytes with the table and appends the result
+    /// onto the end of this `EString`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table does not allow percent-encoded octets.
+    pub fn encode(&mut self, s: &[u8]) {
+        assert!(
+            self.table.allows_pct_encoded(),
+            "table does not allow percent-encoded octets"
+        );
+        for chunk in self.table.encode(s) {
+            self.buf.extend_from_slice(chunk.as_bytes());
+        }
+    }
+
+    /// Appends an `EStr` slice onto the end of this `EString`,
+    /// after validating it with the table.
+    pub fn push_estr(&mut self, s: &EStr) -> Result<(), ValidateError> {
+        crate::validate_detailed(self.table, s.as_bytes())?;
+        self.buf.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    /// Consumes this `EString` and yields the underlying bytes.
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Deref for EString {
+    type Target = EStr;
+
+    fn deref(&self) -> &EStr {
+        self.as_estr()
+    }
+}
+
+impl Borrow<EStr> for EString {
+    fn borrow(&self) -> &EStr {
+        self.as_estr()
+    }
+}
+
+impl AsRef<EStr> for EString {
+    fn as_ref(&self) -> &EStr {
+        self.as_estr()
+    }
+}
+
+impl AsRef<[u8]> for EString {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
@@ -0,0 +1,188 @@
+//! The `application/x-www-form-urlencoded` format of the WHATWG URL Standard.
+
+use super::{DecodedChunk, Encode, EncodedChunk, decode_octet, table_bitset::FORM_URLENCODED};
+use std::{borrow::Cow, iter::FusedIterator};
+
+/// An iterator used to encode a byte string in the `application/x-www-form-urlencoded`
+/// format.
+///
+/// This works like [`Encode`] with [`FORM_URLENCODED`], except that
+/// a space is encoded as `+` (yielded as [`EncodedChunk::PctEncoded`]).
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct FormEncode<'a> {
+    inner: Encode<'a>,
+}
+
+impl<'a> FormEncode<'a> {
+    pub fn new(source: &'a [u8]) -> Self {
+        Self {
+            inner: Encode::new(FORM_URLENCODED, source),
+        }
+    }
+
+    /// Encodes the byte string to bytes.
+    ///
+    /// This method allocates only when the string contains any byte to encode.
+    #[must_use]
+    pub fn to_bytes(mut self) -> Cow<'a, [u8]> {
+        let first = match self.next() {
+            None => return Cow::Borrowed(&[]),
+            Some(EncodedChunk::Unencoded(s)) if self.inner.is_empty() => {
+                return Cow::Borrowed(s);
+            }
+            Some(chunk) => chunk,
+        };
+
+        let mut buf = first.as_bytes().to_vec();
+        for chunk in self {
+            buf.extend_from_slice(chunk.as_bytes());
+        }
+        Cow::Owned(buf)
+    }
+}
+
+impl<'a> Iterator for FormEncode<'a> {
+    type Item = EncodedChunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next()? {
+            EncodedChunk::PctEncoded(b"%20") => Some(EncodedChunk::PctEncoded(b"+")),
+            chunk => Some(chunk),
+        }
+    }
+}
+
+impl FusedIterator for FormEncode<'_> {}
+
+/// An iterator used to decode a byte string in the `application/x-www-form-urlencoded`
+/// format.
+///
+/// This works like [`Decode`](super::Decode), except that a `+` is decoded as a space
+/// (yielded as [`DecodedChunk::PctDecoded`]), and that a `%` not followed by two
+/// hexadecimal digits is left as is instead of being assumed away.
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct FormDecode<'a> {
+    source: &'a [u8],
+}
+
+impl<'a> FormDecode<'a> {
+    pub fn new(source: &'a [u8]) -> Self {
+        Self { source }
+    }
+
+    /// Returns the index of the first `+` or valid triplet in the source.
+    fn next_encoded_idx(&self) -> usize {
+        let s = self.source;
+        let mut i = 0;
+        while i < s.len() {
+            match s[i..] {
+                [b'+', ..] => return i,
+                [b'%', hi, lo, ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                    return i;
+                }
+                _ => i += 1,
+            }
+        }
+        i
+    }
+
+    /// Decodes the byte string to bytes.
+    ///
+    /// This method allocates only when the string contains any `+` or
+    /// percent-encoded octet.
+    #[must_use]
+    pub fn to_bytes(self) -> Cow<'a, [u8]> {
+        if self.next_encoded_idx() == self.source.len() {
+            return Cow::Borrowed(self.source);
+        }
+
+        let mut buf = Vec::with_capacity(self.source.len());
+        for chunk in self {
+            match chunk {
+                DecodedChunk::Unencoded(s) => buf.extend_from_slice(s),
+                DecodedChunk::PctDecoded(x) => buf.push(x),
+            }
+        }
+        Cow::Owned(buf)
+    }
+}
+
+impl<'a> Iterator for FormDecode<'a> {
+    type Item = DecodedChunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.source {
+            [] => None,
+            [b'+', rem @ ..] => {
+                self.source = rem;
+                Some(DecodedChunk::PctDecoded(b' '))
+            }
+            [b'%', hi, lo, rem @ ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                self.source = rem;
+                Some(DecodedChunk::PctDecoded(decode_octet(*hi, *lo)))
+            }
+            _ => {
+                // the first byte is unencoded, whatever follows
+                let i = 1 + FormDecode::new(&self.source[1..]).next_encoded_idx();
+                let (s, rem) = self.source.split_at(i);
+                self.source = rem;
+                Some(DecodedChunk::Unencoded(s))
+            }
+        }
+    }
+}
+
+impl FusedIterator for FormDecode<'_> {}
+
+/// An iterator over the name-value pairs in a byte string in the
+/// `application/x-www-form-urlencoded` format, decoded lazily.
+///
+/// The string is split on `&`, skipping empty sequences, and then each
+/// sequence is split on the first `=`, with the value being empty if there
+/// is no `=`. Names and values are decoded with [`FormDecode`].
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct FormPairs<'a> {
+    source: &'a [u8],
+}
+
+impl<'a> FormPairs<'a> {
+    pub fn new(source: &'a [u8]) -> Self {
+        Self { source }
+    }
+}
+
+impl<'a> Iterator for FormPairs<'a> {
+    type Item = (Cow<'a, [u8]>, Cow<'a, [u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.source.is_empty() {
+                return None;
+            }
+
+            let (seq, rem) = match self.source.iter().position(|&x| x == b'&') {
+                Some(i) => (&self.source[..i], &self.source[i + 1..]),
+                None => (self.source, &[][..]),
+            };
+            self.source = rem;
+
+            if seq.is_empty() {
+                continue;
+            }
+
+            let (name, value) = match seq.iter().position(|&x| x == b'=') {
+                Some(i) => (&seq[..i], &seq[i + 1..]),
+                None => (seq, &[][..]),
+            };
+            return Some((
+                FormDecode::new(name).to_bytes(),
+                FormDecode::new(value).to_bytes(),
+            ));
+        }
+    }
+}
+
+impl FusedIterator for FormPairs<'_> {}
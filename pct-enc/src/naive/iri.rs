@@ -0,0 +1,104 @@
+//! Conversion between IRIs and URIs as specified in RFC 3987 section 3.
+
+use super::{Encode, decode_octet, table_bitset::Table, utf8};
+use std::borrow::Cow;
+
+/// A table that allows every ASCII byte except `%`, which is handled separately.
+const ASCII_EXCEPT_PCT: Table = {
+    let mut bytes = [0; 127];
+    let mut i = 0;
+    while i < 127 {
+        bytes[i] = if i < b'%' as usize { i } else { i + 1 } as u8;
+        i += 1;
+    }
+    Table::new(&bytes)
+};
+
+/// Converts an IRI to a URI by percent-encoding every non-ASCII byte.
+///
+/// ASCII bytes, including any existing percent-encoded octet, are left as is.
+/// This function allocates only when the IRI contains any non-ASCII byte.
+#[must_use]
+pub fn iri_to_uri(iri: &[u8]) -> Cow<'_, [u8]> {
+    let Some(i) = iri.iter().position(|x| !x.is_ascii()) else {
+        return Cow::Borrowed(iri);
+    };
+
+    let non_ascii = iri[i..].iter().filter(|x| !x.is_ascii()).count();
+    let mut buf = Vec::with_capacity(iri.len() + non_ascii * 2);
+    buf.extend_from_slice(&iri[..i]);
+
+    for (j, s) in iri[i..].split(|&x| x == b'%').enumerate() {
+        if j != 0 {
+            buf.push(b'%');
+        }
+        for chunk in Encode::new(ASCII_EXCEPT_PCT, s) {
+            buf.extend_from_slice(chunk.as_bytes());
+        }
+    }
+    Cow::Owned(buf)
+}
+
+/// Decodes the percent-encoded character starting at `s[i]`, returning it along
+/// with the length of its encoding, if the octets there form a non-ASCII
+/// character in UTF-8.
+fn decode_pct_char(s: &[u8], i: usize) -> Option<(char, usize)> {
+    let mut octets = [0; 4];
+    let mut len = 0;
+    while len < 4 {
+        let [b'%', hi, lo, ..] = s[i + len * 3..] else {
+            break;
+        };
+        if !hi.is_ascii_hexdigit() || !lo.is_ascii_hexdigit() {
+            break;
+        }
+        octets[len] = decode_octet(hi, lo);
+        len += 1;
+    }
+    if len == 0 {
+        return None;
+    }
+
+    match utf8::next_code_point(&octets[..len], 0) {
+        Some((c, n)) if !c.is_ascii() => Some((c, n * 3)),
+        _ => None,
+    }
+}
+
+/// Converts a URI to an IRI by decoding the percent-encoded octets that form
+/// a UTF-8 encoded `ucschar`.
+///
+/// Everything else is left as is, including octets that are not part of
+/// a well-formed UTF-8 sequence, percent-encoded ASCII characters, `iprivate`
+/// characters and bidirectional formatting characters.
+/// This function allocates only when there is anything to decode.
+#[must_use]
+pub fn uri_to_iri(uri: &[u8]) -> Cow<'_, [u8]> {
+    let mut buf = Vec::new();
+    // the index up to which `uri` has been copied to `buf`
+    let mut copied = 0;
+
+    let mut i = 0;
+    while let Some(j) = uri[i..].iter().position(|&x| x == b'%') {
+        i += j;
+        match decode_pct_char(uri, i) {
+            Some((c, len)) if utf8::is_ucschar(c) && !utf8::is_bidi_format(c) => {
+                if buf.is_empty() {
+                    buf.reserve_exact(uri.len());
+                }
+                buf.extend_from_slice(&uri[copied..i]);
+                buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                i += len;
+                copied = i;
+            }
+            _ => i += 1,
+        }
+    }
+
+    if copied == 0 {
+        Cow::Borrowed(uri)
+    } else {
+        buf.extend_from_slice(&uri[copied..]);
+        Cow::Owned(buf)
+    }
+}
@@ -1,9 +1,22 @@
 //! Percent-encoding utilities.
 
+mod estr;
+mod form;
+mod iri;
 pub mod table_bitset;
 pub mod table_bool_array;
+pub(crate) mod utf8;
 
-use std::borrow::Cow;
+pub use estr::{EStr, EString, Split};
+pub use form::{FormDecode, FormEncode, FormPairs};
+pub use iri::{iri_to_uri, uri_to_iri};
+
+use crate::{ValidateError, ValidateErrorKind};
+use std::{
+    borrow::Cow,
+    slice,
+    str::{self, Utf8Error},
+};
 
 const fn gen_octet_table(hi: bool) -> [u8; 256] {
     let mut out = [0xff; 256];
@@ -22,8 +35,136 @@ const fn gen_octet_table(hi: bool) -> [u8; 256] {
     out
 }
 
-const OCTET_TABLE_HI: &[u8; 256] = &gen_octet_table(true);
-const OCTET_TABLE_LO: &[u8; 256] = &gen_octet_table(false);
+pub(crate) const OCTET_TABLE_HI: &[u8; 256] = &gen_octet_table(true);
+pub(crate) const OCTET_TABLE_LO: &[u8; 256] = &gen_octet_table(false);
+
+/// A table specifying the byte patterns allowed in a string.
+///
+/// This trait is implemented by both [`table_bitset::Table`] and
+/// [`table_bool_array::Table`]. Functions taking any implementor work on
+/// the bitset form, into which the table is converted first.
+pub trait Table: Copy {
+    /// Checks whether the byte is allowed unencoded by the table.
+    ///
+    /// This is always `false` for `%` and non-ASCII bytes.
+    fn allows(self, x: u8) -> bool;
+
+    /// Checks whether percent-encoded octets are allowed by the table.
+    fn allows_pct_encoded(self) -> bool;
+
+    /// Checks whether `ucschar` is allowed by the table.
+    fn allows_ucschar(self) -> bool;
+
+    /// Checks whether `iprivate` is allowed by the table.
+    fn allows_iprivate(self) -> bool;
+
+    /// Checks whether any non-ASCII character is allowed by the table.
+    fn allows_non_ascii(self) -> bool {
+        self.allows_ucschar() || self.allows_iprivate()
+    }
+
+    /// Validates the given string with the table.
+    fn validate(self, s: &[u8]) -> bool {
+        let mut i = 0;
+
+        macro_rules! do_loop {
+            ($allow_pct_encoded:expr) => {
+                while i < s.len() {
+                    let x = s[i];
+                    if $allow_pct_encoded && x == b'%' {
+                        let [hi, lo, ..] = s[i + 1..] else {
+                            return false;
+                        };
+                        if !is_valid_octet(hi, lo) {
+                            return false;
+                        }
+                        i += 3;
+                    } else if self.allows(x) {
+                        i += 1;
+                    } else if let Some(len) = non_ascii_len(self, s, i) {
+                        i += len;
+                    } else {
+                        return false;
+                    }
+                }
+            };
+        }
+
+        // This expansion alone doesn't help much, but combined with
+        // `#[inline(always)]` on `utf8::next_code_point`,
+        // it improves performance significantly for non-ASCII case.
+        if self.allows_pct_encoded() {
+            do_loop!(true);
+        } else {
+            do_loop!(false);
+        }
+
+        true
+    }
+
+    /// Validates the given string with the table, returning the offset
+    /// and kind of the first error on failure.
+    fn validate_detailed(self, s: &[u8]) -> Result<(), ValidateError> {
+        let mut i = 0;
+        while i < s.len() {
+            let x = s[i];
+            if x == b'%' && self.allows_pct_encoded() {
+                for j in i + 1..i + 3 {
+                    match s.get(j) {
+                        None => {
+                            return Err(ValidateError::new(i, ValidateErrorKind::TruncatedTriplet));
+                        }
+                        Some(x) if !x.is_ascii_hexdigit() => {
+                            return Err(ValidateError::new(j, ValidateErrorKind::NonHexDigit));
+                        }
+                        _ => {}
+                    }
+                }
+                i += 3;
+            } else if self.allows(x) {
+                i += 1;
+            } else if x >= 128 && self.allows_non_ascii() {
+                match utf8::next_code_point(s, i) {
+                    Some((c, len)) if allows_code_point(self, c) => i += len,
+                    Some(_) => {
+                        return Err(ValidateError::new(
+                            i,
+                            ValidateErrorKind::DisallowedCodePoint,
+                        ));
+                    }
+                    None => return Err(ValidateError::new(i, ValidateErrorKind::InvalidUtf8)),
+                }
+            } else {
+                return Err(ValidateError::disallowed(i, x));
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the table into the bitset form.
+    fn to_bitset(self) -> table_bitset::Table;
+
+    /// Converts a table in the bitset form into this form.
+    fn from_bitset(table: table_bitset::Table) -> Self;
+}
+
+#[inline]
+fn allows_code_point(table: impl Table, c: char) -> bool {
+    (table.allows_ucschar() && utf8::is_ucschar(c))
+        || (table.allows_iprivate() && utf8::is_iprivate(c))
+}
+
+/// Returns the length of the allowed non-ASCII character starting at `s[i]`.
+#[inline(always)]
+fn non_ascii_len(table: impl Table, s: &[u8], i: usize) -> Option<usize> {
+    if !table.allows_non_ascii() {
+        return None;
+    }
+    match utf8::next_code_point(s, i) {
+        Some((c, len)) if allows_code_point(table, c) => Some(len),
+        _ => None,
+    }
+}
 
 /// Decodes a percent-encoded octet, assuming that the bytes are hexadecimal.
 fn decode_octet(hi: u8, lo: u8) -> u8 {
@@ -137,30 +278,176 @@ impl<'a> Decode<'a> {
         }
         Cow::Owned(buf)
     }
+
+    /// Decodes the slice to a string, checking that the decoded bytes are valid UTF-8.
+    ///
+    /// The check is done in the same pass as the decoding if the current CPU
+    /// supports AVX-512 VBMI2. This method allocates only when the slice
+    /// contains any percent-encoded octet.
+    pub fn to_str(self) -> Result<Cow<'a, str>, Utf8Error> {
+        match crate::dispatch::decode_utf8(self.source) {
+            (Cow::Borrowed(s), true) => Ok(Cow::Borrowed(unsafe { str::from_utf8_unchecked(s) })),
+            (Cow::Owned(buf), true) => Ok(Cow::Owned(unsafe { String::from_utf8_unchecked(buf) })),
+            (bytes, false) => Err(str::from_utf8(&bytes).unwrap_err()),
+        }
+    }
+
+    /// Decodes the slice to a string, replacing any invalid UTF-8 sequences
+    /// with `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// This method allocates only when the slice contains any percent-encoded
+    /// octet or invalid UTF-8 sequence.
+    #[must_use]
+    pub fn to_string_lossy(self) -> Cow<'a, str> {
+        match crate::dispatch::decode_utf8(self.source) {
+            (Cow::Borrowed(s), true) => Cow::Borrowed(unsafe { str::from_utf8_unchecked(s) }),
+            (Cow::Owned(buf), true) => Cow::Owned(unsafe { String::from_utf8_unchecked(buf) }),
+            (bytes, false) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+        }
+    }
+}
+
+/// Decodes the percent-encoded octets in the buffer in place,
+/// moving the decoded bytes toward the front.
+///
+/// Returns the length of the decoded bytes, after which the contents
+/// of the buffer are unspecified. A `%` not followed by two hexadecimal
+/// digits is left as is.
+pub fn decode_in_place(buf: &mut [u8]) -> usize {
+    let mut i = 0;
+    let mut j = 0;
+    while let Some(k) = buf[i..].iter().position(|&x| x == b'%') {
+        buf.copy_within(i..i + k, j);
+        i += k;
+        j += k;
+
+        if let [_, hi, lo, ..] = buf[i..]
+            && hi.is_ascii_hexdigit()
+            && lo.is_ascii_hexdigit()
+        {
+            buf[j] = decode_octet(hi, lo);
+            i += 3;
+        } else {
+            buf[j] = b'%';
+            i += 1;
+        }
+        j += 1;
+    }
+    buf.copy_within(i.., j);
+    j + buf.len() - i
+}
+
+/// Validates the slice with the table and decodes it.
+///
+/// This function allocates only when the slice contains any percent-encoded octet.
+pub fn decode_checked(table: impl Table, source: &[u8]) -> Result<Cow<'_, [u8]>, ValidateError> {
+    table.validate_detailed(source)?;
+    Ok(Decode::new(source).to_bytes())
+}
+
+/// Normalizes the percent-encoded octets in the slice as described in
+/// RFC 3986 section 6.2.2: decodes the octets allowed unencoded by the table,
+/// and uppercases the hexadecimal digits of the rest.
+///
+/// A `%` not followed by two hexadecimal digits is left as is.
+/// This function allocates only when anything changes.
+#[must_use]
+pub fn normalize(table: impl Table, source: &[u8]) -> Cow<'_, [u8]> {
+    let table = table.to_bitset();
+    let mut buf = Vec::new();
+    // the index up to which `source` has been copied to `buf`
+    let mut copied = 0;
+
+    let mut i = 0;
+    while let Some(j) = source[i..].iter().position(|&x| x == b'%') {
+        i += j;
+        let [_, hi, lo, ..] = source[i..] else {
+            break;
+        };
+        if !hi.is_ascii_hexdigit() || !lo.is_ascii_hexdigit() {
+            i += 1;
+            continue;
+        }
+
+        let x = decode_octet(hi, lo);
+        let normalized = if table.allows_ascii(x) {
+            slice::from_ref(&x)
+        } else {
+            encode_byte(x, HexCase::Upper)
+        };
+        if normalized != &source[i..i + 3] {
+            if copied == 0 {
+                buf.reserve_exact(source.len());
+            }
+            buf.extend_from_slice(&source[copied..i]);
+            buf.extend_from_slice(normalized);
+            copied = i + 3;
+        }
+        i += 3;
+    }
+
+    if copied == 0 {
+        Cow::Borrowed(source)
+    } else {
+        buf.extend_from_slice(&source[copied..]);
+        Cow::Owned(buf)
+    }
 }
 
-pub(crate) fn encode_byte(x: u8) -> &'static [u8] {
-    const TABLE: &[u8; 256 * 3] = &{
-        const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+/// The case of the hexadecimal digits in percent-encoded octets.
+///
+/// RFC 3986 recommends uppercase, but some schemes compare against
+/// lowercase-encoded strings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HexCase {
+    /// `0123456789ABCDEF`.
+    #[default]
+    Upper,
+    /// `0123456789abcdef`.
+    Lower,
+}
+
+impl HexCase {
+    /// Returns the hexadecimal digits in this case, in ascending order.
+    #[must_use]
+    pub const fn digits(self) -> &'static [u8; 16] {
+        match self {
+            Self::Upper => b"0123456789ABCDEF",
+            Self::Lower => b"0123456789abcdef",
+        }
+    }
+}
+
+pub(crate) fn encode_byte(x: u8, case: HexCase) -> &'static [u8] {
+    const fn gen_table(case: HexCase) -> [u8; 256 * 3] {
+        let hex_digits = case.digits();
 
         let mut i = 0;
         let mut table = [0; 256 * 3];
         while i < 256 {
             table[i * 3] = b'%';
-            table[i * 3 + 1] = HEX_DIGITS[i >> 4];
-            table[i * 3 + 2] = HEX_DIGITS[i & 0b1111];
+            table[i * 3 + 1] = hex_digits[i >> 4];
+            table[i * 3 + 2] = hex_digits[i & 0b1111];
             i += 1;
         }
         table
-    };
+    }
 
-    &TABLE[x as usize * 3..x as usize * 3 + 3]
+    const TABLE_UPPER: &[u8; 256 * 3] = &gen_table(HexCase::Upper);
+    const TABLE_LOWER: &[u8; 256 * 3] = &gen_table(HexCase::Lower);
+
+    let table = match case {
+        HexCase::Upper => TABLE_UPPER,
+        HexCase::Lower => TABLE_LOWER,
+    };
+    &table[x as usize * 3..x as usize * 3 + 3]
 }
 
 /// An iterator used to percent-encode a string slice.
 ///
-/// This struct is created by [`Table::encode`]. Normally you'll use [`EString::encode`]
-/// instead, unless you need precise control over allocation.
+/// This struct is created by [`Table::encode`](table_bitset::Table::encode).
+/// Normally you'll use [`EString::encode`] instead, unless you need precise
+/// control over allocation.
 ///
 /// See the [`EncodedChunk`] type for documentation of the items yielded by this iterator.
 #[derive(Clone, Debug)]
@@ -169,14 +456,54 @@ pub struct Encode<'s> {
     table: table_bitset::Table,
     source: &'s [u8],
     to_enc: &'s [u8],
+    preserve_pct_encoded: bool,
+    hex_case: HexCase,
 }
 
 impl<'s> Encode<'s> {
-    pub fn new(table: table_bitset::Table, source: &'s [u8]) -> Self {
+    pub fn new(table: impl Table, source: &'s [u8]) -> Self {
         Self {
-            table,
+            table: table.to_bitset(),
             source,
             to_enc: &[],
+            preserve_pct_encoded: false,
+            hex_case: HexCase::Upper,
+        }
+    }
+
+    /// Leaves the well-formed percent-encoded octets in the source as is,
+    /// encoding only a `%` not followed by two hexadecimal digits along with
+    /// the bytes not allowed by the table.
+    ///
+    /// This allows fixing up a partially encoded string without encoding it twice,
+    /// for example, `"%20 "` is encoded as `"%20%20"` rather than `"%2520%20"`.
+    pub fn preserve_pct_encoded(mut self) -> Self {
+        self.preserve_pct_encoded = true;
+        self
+    }
+
+    /// Sets the case of the hexadecimal digits in the encoded octets,
+    /// which is [`HexCase::Upper`] by default.
+    ///
+    /// Preserved triplets are left in their original case.
+    pub fn hex_case(mut self, case: HexCase) -> Self {
+        self.hex_case = case;
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.source.is_empty() && self.to_enc.is_empty()
+    }
+
+    /// Returns the length of the unencoded unit at the start of `s`,
+    /// which is a byte allowed by the table or a preserved triplet,
+    /// or 0 if there is none.
+    #[inline(always)]
+    fn unencoded_len(&self, s: &[u8]) -> usize {
+        match *s {
+            [x, ..] if self.table.allows_ascii(x) => 1,
+            [b'%', hi, lo, ..] if self.preserve_pct_encoded && is_valid_octet(hi, lo) => 3,
+            _ => 0,
         }
     }
 }
@@ -206,21 +533,23 @@ impl<'a> Iterator for Encode<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if let [x, rem @ ..] = self.to_enc {
             self.to_enc = rem;
-            return Some(EncodedChunk::PctEncoded(encode_byte(*x)));
+            return Some(EncodedChunk::PctEncoded(encode_byte(*x, self.hex_case)));
         }
 
         if self.source.is_empty() {
             return None;
         }
 
-        let mut iter = self.source.iter().copied().enumerate();
-
-        let first_disallowed_idx = iter
-            .find_map(|(i, x)| (!self.table.allows_ascii(x)).then_some(i))
-            .unwrap_or(self.source.len());
+        let mut first_disallowed_idx = 0;
+        loop {
+            match self.unencoded_len(&self.source[first_disallowed_idx..]) {
+                0 => break,
+                n => first_disallowed_idx += n,
+            }
+        }
 
-        let next_allowed_idx = iter
-            .find_map(|(i, x)| self.table.allows_ascii(x).then_some(i))
+        let next_allowed_idx = (first_disallowed_idx..self.source.len())
+            .find(|&i| self.unencoded_len(&self.source[i..]) != 0)
             .unwrap_or(self.source.len());
 
         if first_disallowed_idx == 0 {
@@ -230,7 +559,7 @@ impl<'a> Iterator for Encode<'a> {
             let (x, rem) = disallowed.split_first().unwrap();
             self.to_enc = rem;
 
-            Some(EncodedChunk::PctEncoded(encode_byte(*x)))
+            Some(EncodedChunk::PctEncoded(encode_byte(*x, self.hex_case)))
         } else {
             let allowed = &self.source[..first_disallowed_idx];
             self.to_enc = &self.source[first_disallowed_idx..next_allowed_idx];
@@ -5,11 +5,20 @@
 //!
 //! [RFC 5234]: https://datatracker.ietf.org/doc/html/rfc5234
 
+use super::Encode;
+use crate::ValidateError;
+use std::{fmt, iter::FusedIterator};
+
 const MASK_PCT_ENCODED: u64 = 1 << b'%';
+const FLAG_UCSCHAR: u8 = 1;
+const FLAG_IPRIVATE: u8 = 2;
 
 /// A table specifying the byte patterns allowed in a string.
-#[derive(Clone, Copy, Debug)]
-pub struct Table(u64, u64);
+///
+/// The table is formatted as an ABNF alternation of the patterns it allows,
+/// for example `%x30-39 / "-" / pct-encoded`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Table(u64, u64, u8);
 
 impl Table {
     /// Creates a table that only allows the given unencoded bytes.
@@ -28,13 +37,19 @@ impl Table {
             table |= 1u128.wrapping_shl(*cur as u32);
             bytes = rem;
         }
-        Self(table as u64, (table >> 64) as u64)
+        Self(table as u64, (table >> 64) as u64, 0)
     }
 
     pub const fn bits(self) -> (u64, u64) {
         (self.0, self.1)
     }
 
+    /// Creates a table from the bits returned by [`Table::bits`],
+    /// allowing no non-ASCII character.
+    pub(crate) const fn from_bits(bits: (u64, u64)) -> Self {
+        Self(bits.0, bits.1, 0)
+    }
+
     pub const fn bits_transposed(self) -> (u64, u64) {
         let x = (self.1 as u128) << 64 | self.0 as u128;
         let mut y = 0;
@@ -56,13 +71,55 @@ impl Table {
     /// by `self` or by `other`.
     #[must_use]
     pub const fn or(self, other: Self) -> Self {
-        Self(self.0 | other.0, self.1 | other.1)
+        Self(self.0 | other.0, self.1 | other.1, self.2 | other.2)
+    }
+
+    /// Intersects two tables.
+    ///
+    /// Returns a new table that allows the byte patterns allowed
+    /// by both `self` and `other`.
+    #[must_use]
+    pub const fn and(self, other: Self) -> Self {
+        Self(self.0 & other.0, self.1 & other.1, self.2 & other.2)
+    }
+
+    /// Subtracts a table from another.
+    ///
+    /// Returns a new table that allows the byte patterns allowed
+    /// by `self` but not by `other`.
+    #[must_use]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0, self.1 & !other.1, self.2 & !other.2)
+    }
+
+    /// Complements the unencoded ASCII bytes allowed by this table.
+    ///
+    /// Returns a new table that allows the ASCII bytes other than `%`
+    /// not allowed unencoded by `self`. Whether percent-encoded octets
+    /// and non-ASCII characters are allowed is left unchanged.
+    #[must_use]
+    pub const fn complement_ascii(self) -> Self {
+        Self(self.0 ^ !MASK_PCT_ENCODED, !self.1, self.2)
     }
 
     /// Marks this table as allowing percent-encoded octets.
     #[must_use]
     pub const fn or_pct_encoded(self) -> Self {
-        Self(self.0 | MASK_PCT_ENCODED, self.1)
+        Self(self.0 | MASK_PCT_ENCODED, self.1, self.2)
+    }
+
+    /// Marks this table as allowing characters matching the `ucschar` rule
+    /// from RFC 3987, encoded in UTF-8.
+    #[must_use]
+    pub const fn or_ucschar(self) -> Self {
+        Self(self.0, self.1, self.2 | FLAG_UCSCHAR)
+    }
+
+    /// Marks this table as allowing characters matching the `iprivate` rule
+    /// from RFC 3987, encoded in UTF-8.
+    #[must_use]
+    pub const fn or_iprivate(self) -> Self {
+        Self(self.0, self.1, self.2 | FLAG_IPRIVATE)
     }
 
     #[inline]
@@ -89,6 +146,33 @@ impl Table {
         table & 1u64.wrapping_shl(x as u32) != 0
     }
 
+    /// Checks whether the byte is allowed unencoded by the table.
+    ///
+    /// This is always `false` for `%` and non-ASCII bytes.
+    #[inline]
+    #[must_use]
+    pub const fn contains(self, x: u8) -> bool {
+        self.allows_ascii(x)
+    }
+
+    /// Returns an iterator over the bytes allowed unencoded by the table,
+    /// in ascending order.
+    pub fn iter(self) -> Iter {
+        Iter((self.0 & !MASK_PCT_ENCODED) as u128 | (self.1 as u128) << 64)
+    }
+
+    /// Returns the number of bytes allowed unencoded by the table.
+    #[must_use]
+    pub const fn len(self) -> usize {
+        ((self.0 & !MASK_PCT_ENCODED).count_ones() + self.1.count_ones()) as usize
+    }
+
+    /// Checks whether no byte is allowed unencoded by the table.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
     /// Checks whether percent-encoded octets are allowed by the table.
     #[inline]
     #[must_use]
@@ -96,39 +180,174 @@ impl Table {
         self.0 & MASK_PCT_ENCODED != 0
     }
 
+    /// Checks whether `ucschar` is allowed by the table.
+    #[inline]
+    #[must_use]
+    pub const fn allows_ucschar(self) -> bool {
+        self.2 & FLAG_UCSCHAR != 0
+    }
+
+    /// Checks whether `iprivate` is allowed by the table.
+    #[inline]
+    #[must_use]
+    pub const fn allows_iprivate(self) -> bool {
+        self.2 & FLAG_IPRIVATE != 0
+    }
+
+    /// Checks whether any non-ASCII character is allowed by the table.
+    #[inline]
+    #[must_use]
+    pub const fn allows_non_ascii(self) -> bool {
+        self.2 != 0
+    }
+
+    /// Returns an iterator used to percent-encode the given bytes with the table.
+    ///
+    /// Every byte not allowed unencoded, including any non-ASCII byte, is encoded.
+    pub fn encode(self, s: &[u8]) -> Encode<'_> {
+        Encode::new(self, s)
+    }
+
     /// Validates the given string with the table.
     pub fn validate(self, s: &[u8]) -> bool {
-        let mut i = 0;
+        super::Table::validate(self, s)
+    }
+
+    /// Validates the given string with the table, returning the offset
+    /// and kind of the first error on failure.
+    pub fn validate_detailed(self, s: &[u8]) -> Result<(), ValidateError> {
+        super::Table::validate_detailed(self, s)
+    }
+}
+
+impl super::Table for Table {
+    #[inline]
+    fn allows(self, x: u8) -> bool {
+        self.allows_ascii(x)
+    }
+
+    #[inline]
+    fn allows_pct_encoded(self) -> bool {
+        self.allows_pct_encoded()
+    }
+
+    #[inline]
+    fn allows_ucschar(self) -> bool {
+        self.allows_ucschar()
+    }
+
+    #[inline]
+    fn allows_iprivate(self) -> bool {
+        self.allows_iprivate()
+    }
+
+    #[inline]
+    fn allows_non_ascii(self) -> bool {
+        self.allows_non_ascii()
+    }
+
+    #[inline]
+    fn to_bitset(self) -> Table {
+        self
+    }
+
+    #[inline]
+    fn from_bitset(table: Table) -> Self {
+        table
+    }
+}
 
-        macro_rules! do_loop {
-            ($allow_pct_encoded:expr) => {
-                while i < s.len() {
-                    let x = s[i];
-                    if $allow_pct_encoded && x == b'%' {
-                        let [hi, lo, ..] = s[i + 1..] else {
-                            return false;
-                        };
-                        if !super::is_valid_octet(hi, lo) {
-                            return false;
-                        }
-                        i += 3;
-                    } else {
-                        if !self.allows_ascii(x) {
-                            return false;
-                        }
-                        i += 1;
-                    }
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut alt = |f: &mut fmt::Formatter<'_>, args: fmt::Arguments<'_>| {
+            if !first {
+                f.write_str(" / ")?;
+            }
+            first = false;
+            f.write_fmt(args)
+        };
+
+        let mut iter = self.iter().peekable();
+        while let Some(start) = iter.next() {
+            let mut end = start;
+            while iter.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+
+            if end - start >= 2 {
+                alt(f, format_args!("%x{start:02X}-{end:02X}"))?;
+                continue;
+            }
+            for x in start..=end {
+                // quoted strings are case-insensitive in ABNF
+                if x.is_ascii_graphic() && !x.is_ascii_alphabetic() && x != b'"' {
+                    alt(f, format_args!("\"{}\"", x as char))?;
+                } else {
+                    alt(f, format_args!("%x{x:02X}"))?;
                 }
-            };
+            }
         }
 
-        if self.allows_pct_encoded() {
-            do_loop!(true);
-        } else {
-            do_loop!(false);
+        for (allowed, name) in [
+            (self.allows_pct_encoded(), "pct-encoded"),
+            (self.allows_ucschar(), "ucschar"),
+            (self.allows_iprivate(), "iprivate"),
+        ] {
+            if allowed {
+                alt(f, format_args!("{name}"))?;
+            }
+        }
+
+        if first {
+            // the table allows only the empty string
+            f.write_str("\"\"")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Table({self})")
+    }
+}
+
+/// An iterator over the bytes allowed unencoded by a [`Table`].
+///
+/// This struct is created by [`Table::iter`].
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct Iter(u128);
+
+impl Iterator for Iter {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
         }
+        let x = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Iter {}
 
-        true
+impl FusedIterator for Iter {}
+
+impl IntoIterator for Table {
+    type Item = u8;
+    type IntoIter = Iter;
+
+    fn into_iter(self) -> Iter {
+        self.iter()
     }
 }
 
@@ -147,15 +366,81 @@ pub const DIGIT: Table = new(b"0123456789");
 /// `HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"`
 pub const HEXDIG: Table = DIGIT.or(new(b"ABCDEFabcdef"));
 
+/// `scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )`
+pub const SCHEME: Table = ALPHA.or(DIGIT).or(new(b"+-."));
+
+/// `userinfo = *( unreserved / pct-encoded / sub-delims / ":" )`
+pub const USERINFO: Table = UNRESERVED.or(SUB_DELIMS).or(new(b":")).or_pct_encoded();
+
+/// `IPvFuture = "v" 1*HEXDIG "." 1*( unreserved / sub-delims / ":" )`
+pub const IPV_FUTURE: Table = UNRESERVED.or(SUB_DELIMS).or(new(b":"));
+
+/// `reg-name = *( unreserved / pct-encoded / sub-delims )`
+pub const REG_NAME: Table = UNRESERVED.or(SUB_DELIMS).or_pct_encoded();
+
 /// `path = *( pchar / "/" )`
 pub const PATH: Table = PCHAR.or(new(b"/"));
 
 /// `pchar = unreserved / pct-encoded / sub-delims / ":" / "@"`
 pub const PCHAR: Table = UNRESERVED.or(SUB_DELIMS).or(new(b":@")).or_pct_encoded();
 
+/// `segment-nz-nc = 1*( unreserved / pct-encoded / sub-delims / "@" )`
+pub const SEGMENT_NZ_NC: Table = UNRESERVED.or(SUB_DELIMS).or(new(b"@")).or_pct_encoded();
+
+/// `query = *( pchar / "/" / "?" )`
+pub const QUERY: Table = PCHAR.or(new(b"/?"));
+
+/// `fragment = *( pchar / "/" / "?" )`
+pub const FRAGMENT: Table = QUERY;
+
 /// `unreserved = ALPHA / DIGIT / "-" / "." / "_" / "~"`
 pub const UNRESERVED: Table = ALPHA.or(DIGIT).or(new(b"-._~"));
 
+/// `reserved = gen-delims / sub-delims`
+pub const RESERVED: Table = GEN_DELIMS.or(SUB_DELIMS);
+
+/// `gen-delims = ":" / "/" / "?" / "#" / "[" / "]" / "@"`
+pub const GEN_DELIMS: Table = new(b":/?#[]@");
+
 /// `sub-delims = "!" / "$" / "&" / "'" / "(" / ")"
 ///             / "*" / "+" / "," / ";" / "="`
 pub const SUB_DELIMS: Table = new(b"!$&'()*+,;=");
+
+// Rules from RFC 6874:
+
+/// `ZoneID = 1*( unreserved / pct-encoded )`
+pub const ZONE_ID: Table = UNRESERVED.or_pct_encoded();
+
+// Byte sets from the WHATWG URL Standard:
+
+/// The bytes left unencoded by the `application/x-www-form-urlencoded` serializer,
+/// that is, the complement of its percent-encode set, less the space encoded as `+`.
+pub const FORM_URLENCODED: Table = ALPHA.or(DIGIT).or(new(b"*-._"));
+
+// Rules from RFC 3987:
+
+/// `ucschar = %xA0-D7FF / %xF900-FDCF / %xFDF0-FFEF
+///          / %x10000-1FFFD / %x20000-2FFFD / %x30000-3FFFD
+///          / %x40000-4FFFD / %x50000-5FFFD / %x60000-6FFFD
+///          / %x70000-7FFFD / %x80000-8FFFD / %x90000-9FFFD
+///          / %xA0000-AFFFD / %xB0000-BFFFD / %xC0000-CFFFD
+///          / %xD0000-DFFFD / %xE1000-EFFFD`
+pub const UCSCHAR: Table = new(b"").or_ucschar();
+
+/// `iprivate = %xE000-F8FF / %xF0000-FFFFD / %x100000-10FFFD`
+pub const IPRIVATE: Table = new(b"").or_iprivate();
+
+/// `iunreserved = ALPHA / DIGIT / "-" / "." / "_" / "~" / ucschar`
+pub const IUNRESERVED: Table = UNRESERVED.or(UCSCHAR);
+
+/// `ipchar = iunreserved / pct-encoded / sub-delims / ":" / "@"`
+pub const IPCHAR: Table = IUNRESERVED.or(SUB_DELIMS).or(new(b":@")).or_pct_encoded();
+
+/// `ipath = *( ipchar / "/" )`
+pub const IPATH: Table = IPCHAR.or(new(b"/"));
+
+/// `iquery = *( ipchar / iprivate / "/" / "?" )`
+pub const IQUERY: Table = IPCHAR.or(IPRIVATE).or(new(b"/?"));
+
+/// `ifragment = *( ipchar / "/" / "?" )`
+pub const IFRAGMENT: Table = IPCHAR.or(new(b"/?"));
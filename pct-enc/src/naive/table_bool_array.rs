@@ -5,12 +5,17 @@
 //!
 //! [RFC 5234]: https://datatracker.ietf.org/doc/html/rfc5234
 
-const TABLE_LEN: usize = 257;
+use super::{Encode, table_bitset};
+use crate::ValidateError;
+
+const TABLE_LEN: usize = 259;
 const INDEX_PCT_ENCODED: usize = 256;
+const INDEX_UCSCHAR: usize = 257;
+const INDEX_IPRIVATE: usize = 258;
 
 /// A table specifying the byte patterns allowed in a string.
 #[derive(Clone, Copy, Debug)]
-pub struct Table([bool; 257]);
+pub struct Table([bool; TABLE_LEN]);
 
 impl Table {
     /// Creates a table that only allows the given unencoded bytes.
@@ -53,6 +58,22 @@ impl Table {
         self
     }
 
+    /// Marks this table as allowing characters matching the `ucschar` rule
+    /// from RFC 3987, encoded in UTF-8.
+    #[must_use]
+    pub const fn or_ucschar(mut self) -> Self {
+        self.0[INDEX_UCSCHAR] = true;
+        self
+    }
+
+    /// Marks this table as allowing characters matching the `iprivate` rule
+    /// from RFC 3987, encoded in UTF-8.
+    #[must_use]
+    pub const fn or_iprivate(mut self) -> Self {
+        self.0[INDEX_IPRIVATE] = true;
+        self
+    }
+
     #[inline]
     pub(crate) const fn allows_ascii(self, x: u8) -> bool {
         self.0[x as usize]
@@ -65,69 +86,212 @@ impl Table {
         self.0[INDEX_PCT_ENCODED]
     }
 
-    /// Validates the given string with the table.
-    pub fn validate(&self, s: &[u8]) -> bool {
+    /// Checks whether `ucschar` is allowed by the table.
+    #[inline]
+    #[must_use]
+    pub const fn allows_ucschar(self) -> bool {
+        self.0[INDEX_UCSCHAR]
+    }
+
+    /// Checks whether `iprivate` is allowed by the table.
+    #[inline]
+    #[must_use]
+    pub const fn allows_iprivate(self) -> bool {
+        self.0[INDEX_IPRIVATE]
+    }
+
+    /// Checks whether any non-ASCII character is allowed by the table.
+    #[inline]
+    #[must_use]
+    pub const fn allows_non_ascii(self) -> bool {
+        self.allows_ucschar() || self.allows_iprivate()
+    }
+
+    /// Converts the table into the bitset form.
+    #[must_use]
+    pub const fn to_bitset(self) -> table_bitset::Table {
+        // the entry for % is that for percent-encoded octets in both forms
+        let mut bits = [0u64; 2];
         let mut i = 0;
+        while i < 128 {
+            if self.0[i] || (i == b'%' as usize && self.allows_pct_encoded()) {
+                bits[i / 64] |= 1 << (i % 64);
+            }
+            i += 1;
+        }
 
-        macro_rules! do_loop {
-            ($allow_pct_encoded:expr) => {
-                while i < s.len() {
-                    let x = s[i];
-                    if $allow_pct_encoded && x == b'%' {
-                        let [hi, lo, ..] = s[i + 1..] else {
-                            return false;
-                        };
-                        if !super::is_valid_octet(hi, lo) {
-                            return false;
-                        }
-                        i += 3;
-                    } else {
-                        if !self.allows_ascii(x) {
-                            return false;
-                        }
-                        i += 1;
-                    }
-                }
-            };
+        let mut table = table_bitset::Table::from_bits((bits[0], bits[1]));
+        if self.allows_ucschar() {
+            table = table.or_ucschar();
+        }
+        if self.allows_iprivate() {
+            table = table.or_iprivate();
         }
+        table
+    }
 
-        // This expansion alone doesn't help much, but combined with
-        // `#[inline(always)]` on `utf8::next_code_point`,
-        // it improves performance significantly for non-ASCII case.
-        if self.allows_pct_encoded() {
-            do_loop!(true);
-        } else {
-            do_loop!(false);
+    /// Converts a table in the bitset form into this form.
+    #[must_use]
+    pub const fn from_bitset(table: table_bitset::Table) -> Self {
+        let mut out = [false; TABLE_LEN];
+        let mut i = 0;
+        while i < 128 {
+            out[i] = table.allows_ascii(i as u8);
+            i += 1;
         }
+        out[INDEX_PCT_ENCODED] = table.allows_pct_encoded();
+        out[INDEX_UCSCHAR] = table.allows_ucschar();
+        out[INDEX_IPRIVATE] = table.allows_iprivate();
+        Self(out)
+    }
+
+    /// Returns an iterator used to percent-encode the given bytes with the table.
+    ///
+    /// Every byte not allowed unencoded, including any non-ASCII byte, is encoded.
+    pub fn encode(self, s: &[u8]) -> Encode<'_> {
+        Encode::new(self, s)
+    }
 
-        true
+    /// Validates the given string with the table.
+    pub fn validate(&self, s: &[u8]) -> bool {
+        super::Table::validate(*self, s)
+    }
+
+    /// Validates the given string with the table, returning the offset
+    /// and kind of the first error on failure.
+    pub fn validate_detailed(&self, s: &[u8]) -> Result<(), ValidateError> {
+        super::Table::validate_detailed(*self, s)
     }
 }
 
-const fn new(bytes: &[u8]) -> Table {
-    Table::new(bytes)
+impl super::Table for Table {
+    #[inline]
+    fn allows(self, x: u8) -> bool {
+        self.allows_ascii(x)
+    }
+
+    #[inline]
+    fn allows_pct_encoded(self) -> bool {
+        self.allows_pct_encoded()
+    }
+
+    #[inline]
+    fn allows_ucschar(self) -> bool {
+        self.allows_ucschar()
+    }
+
+    #[inline]
+    fn allows_iprivate(self) -> bool {
+        self.allows_iprivate()
+    }
+
+    #[inline]
+    fn to_bitset(self) -> table_bitset::Table {
+        Table::to_bitset(self)
+    }
+
+    #[inline]
+    fn from_bitset(table: table_bitset::Table) -> Self {
+        Table::from_bitset(table)
+    }
+}
+
+/// Defines the constants from those of the same names in [`table_bitset`].
+macro_rules! from_bitset {
+    ($($(#[$doc:meta])* $name:ident,)+) => {$(
+        $(#[$doc])*
+        pub const $name: Table = Table::from_bitset(table_bitset::$name);
+    )+};
 }
 
-// Rules from RFC 3986:
+from_bitset!(
+    // Rules from RFC 3986:
+
+    /// `ALPHA = %x41-5A / %x61-7A`
+    ALPHA,
+
+    /// `DIGIT = %x30-39`
+    DIGIT,
+
+    /// `HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"`
+    HEXDIG,
+
+    /// `scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )`
+    SCHEME,
+
+    /// `userinfo = *( unreserved / pct-encoded / sub-delims / ":" )`
+    USERINFO,
+
+    /// `IPvFuture = "v" 1*HEXDIG "." 1*( unreserved / sub-delims / ":" )`
+    IPV_FUTURE,
+
+    /// `reg-name = *( unreserved / pct-encoded / sub-delims )`
+    REG_NAME,
+
+    /// `path = *( pchar / "/" )`
+    PATH,
+
+    /// `pchar = unreserved / pct-encoded / sub-delims / ":" / "@"`
+    PCHAR,
+
+    /// `segment-nz-nc = 1*( unreserved / pct-encoded / sub-delims / "@" )`
+    SEGMENT_NZ_NC,
+
+    /// `query = *( pchar / "/" / "?" )`
+    QUERY,
+
+    /// `fragment = *( pchar / "/" / "?" )`
+    FRAGMENT,
+
+    /// `unreserved = ALPHA / DIGIT / "-" / "." / "_" / "~"`
+    UNRESERVED,
+
+    /// `reserved = gen-delims / sub-delims`
+    RESERVED,
+
+    /// `gen-delims = ":" / "/" / "?" / "#" / "[" / "]" / "@"`
+    GEN_DELIMS,
+
+    /// `sub-delims = "!" / "$" / "&" / "'" / "(" / ")"
+    ///             / "*" / "+" / "," / ";" / "="`
+    SUB_DELIMS,
+
+    // Rules from RFC 6874:
+
+    /// `ZoneID = 1*( unreserved / pct-encoded )`
+    ZONE_ID,
+
+    // Byte sets from the WHATWG URL Standard:
+
+    /// The bytes left unencoded by the `application/x-www-form-urlencoded` serializer,
+    /// that is, the complement of its percent-encode set, less the space encoded as `+`.
+    FORM_URLENCODED,
+
+    // Rules from RFC 3987:
 
-/// `ALPHA = %x41-5A / %x61-7A`
-pub const ALPHA: Table = new(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz");
+    /// `ucschar = %xA0-D7FF / %xF900-FDCF / %xFDF0-FFEF
+    ///          / %x10000-1FFFD / %x20000-2FFFD / %x30000-3FFFD
+    ///          / %x40000-4FFFD / %x50000-5FFFD / %x60000-6FFFD
+    ///          / %x70000-7FFFD / %x80000-8FFFD / %x90000-9FFFD
+    ///          / %xA0000-AFFFD / %xB0000-BFFFD / %xC0000-CFFFD
+    ///          / %xD0000-DFFFD / %xE1000-EFFFD`
+    UCSCHAR,
 
-/// `DIGIT = %x30-39`
-pub const DIGIT: Table = new(b"0123456789");
+    /// `iprivate = %xE000-F8FF / %xF0000-FFFFD / %x100000-10FFFD`
+    IPRIVATE,
 
-/// `HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"`
-pub const HEXDIG: Table = DIGIT.or(new(b"ABCDEFabcdef"));
+    /// `iunreserved = ALPHA / DIGIT / "-" / "." / "_" / "~" / ucschar`
+    IUNRESERVED,
 
-/// `path = *( pchar / "/" )`
-pub const PATH: Table = PCHAR.or(new(b"/"));
+    /// `ipchar = iunreserved / pct-encoded / sub-delims / ":" / "@"`
+    IPCHAR,
 
-/// `pchar = unreserved / pct-encoded / sub-delims / ":" / "@"`
-pub const PCHAR: Table = UNRESERVED.or(SUB_DELIMS).or(new(b":@")).or_pct_encoded();
+    /// `ipath = *( ipchar / "/" )`
+    IPATH,
 
-/// `unreserved = ALPHA / DIGIT / "-" / "." / "_" / "~"`
-pub const UNRESERVED: Table = ALPHA.or(DIGIT).or(new(b"-._~"));
+    /// `iquery = *( ipchar / iprivate / "/" / "?" )`
+    IQUERY,
 
-/// `sub-delims = "!" / "$" / "&" / "'" / "(" / ")"
-///             / "*" / "+" / "," / ";" / "="`
-pub const SUB_DELIMS: Table = new(b"!$&'()*+,;=");
+    /// `ifragment = *( ipchar / "/" / "?" )`
+    IFRAGMENT,
+);
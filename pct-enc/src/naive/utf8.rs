@@ -0,0 +1,49 @@
+//! UTF-8 utilities for RFC 3987.
+
+use std::str;
+
+/// Decodes the code point starting at `s[i]`, returning it along with
+/// its length in bytes, or `None` if the bytes there are not well-formed UTF-8.
+#[inline(always)]
+pub(crate) fn next_code_point(s: &[u8], i: usize) -> Option<(char, usize)> {
+    let len = match s[i] {
+        0..=0x7f => 1,
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return None,
+    };
+    let c = str::from_utf8(s.get(i..i + len)?).ok()?.chars().next()?;
+    Some((c, len))
+}
+
+/// Returns the start of the UTF-8 sequence that `s[i]` may continue,
+/// or `i` if there is no such sequence.
+///
+/// Only looks back as far as a well-formed sequence can reach.
+#[inline(always)]
+pub(crate) fn floor_seq_start(s: &[u8], i: usize) -> usize {
+    let mut j = i;
+    while j > 0 && i - j < 3 && s[j - 1] & 0xc0 == 0x80 {
+        j -= 1;
+    }
+    if j > 0 && s[j - 1] >= 0xc0 { j - 1 } else { i }
+}
+
+/// Checks whether the code point matches the `ucschar` rule.
+pub(crate) const fn is_ucschar(c: char) -> bool {
+    matches!(c as u32,
+        0xa0..=0xd7ff | 0xf900..=0xfdcf | 0xfdf0..=0xffef | 0xe1000..=0xefffd
+    ) || matches!(c as u32, 0x10000..=0xdfffd if c as u32 & 0xfffe != 0xfffe)
+}
+
+/// Checks whether the code point matches the `iprivate` rule.
+pub(crate) const fn is_iprivate(c: char) -> bool {
+    matches!(c as u32, 0xe000..=0xf8ff | 0xf0000..=0xffffd | 0x100000..=0x10fffd)
+}
+
+/// Checks whether the code point is a bidirectional formatting character,
+/// which must not appear in an IRI according to RFC 3987 section 4.1.
+pub(crate) const fn is_bidi_format(c: char) -> bool {
+    matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}')
+}
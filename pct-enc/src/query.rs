@@ -0,0 +1,301 @@
+//! Splitting of query strings into key-value pairs.
+//!
+//! The splitter finds the delimiters a block of bytes at a time
+//! with the same shuffle-based lookups as the validators,
+//! walking the resulting bitmask with `trailing_zeros`.
+//! The blocks are scanned in batches, so that the lookup tables
+//! are set up once per batch rather than once per block.
+
+use crate::{
+    ValidateError, avx2, avx512,
+    dispatch::{self, Backend},
+    naive::{self, table_bitset::Table},
+};
+use std::ops::Range;
+
+/// The bytes that delimit the pairs (`&` and `;`) and the key and value
+/// within a pair (`=`).
+pub const DELIMITERS: Table = Table::new(b"&;=");
+
+/// A key-value pair, as byte ranges into the query string.
+pub type Spans = (Range<usize>, Range<usize>);
+
+/// The number of blocks scanned by a single call to a SIMD kernel.
+const BATCH_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+enum Kernel {
+    Scalar,
+    Avx2,
+    Avx512,
+}
+
+impl Kernel {
+    /// Returns the kernel for the backend, which must be supported by the current CPU.
+    fn new(backend: Backend) -> Self {
+        match backend {
+            Backend::Naive | Backend::Ssse3 | Backend::Sse41 => Self::Scalar,
+            Backend::Avx2 => Self::Avx2,
+            Backend::Avx512 | Backend::Avx512Gfni | Backend::Avx512Vbmi => Self::Avx512,
+        }
+    }
+
+    const fn width(self) -> usize {
+        match self {
+            Self::Scalar | Self::Avx512 => 64,
+            Self::Avx2 => 32,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Scanner<'a> {
+    src: &'a [u8],
+    kernel: Kernel,
+    /// The table to validate the rest of the string with, if any.
+    table: Option<Table>,
+    /// The index of the next block to scan.
+    pos: usize,
+    /// The bitmasks of delimiters and invalid bytes of a batch of blocks
+    /// scanned by the kernel, of which the one at `batch_pos` is at `pos`.
+    batch: [(u64, u64); BATCH_LEN],
+    batch_pos: usize,
+    batch_len: usize,
+    /// The index of the current block.
+    base: usize,
+    /// The bitmask of the delimiters not yet walked in the current block.
+    mask: u64,
+    after_pct_prev: u64,
+    /// The index at which scanning stops, that is, the offset of the error
+    /// if any, or the length of the string.
+    limit: usize,
+    err: Option<ValidateError>,
+    pair_start: usize,
+    eq: Option<usize>,
+    finished: bool,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(src: &'a [u8], table: Option<Table>) -> Self {
+        Self {
+            src,
+            kernel: Kernel::new(dispatch::backend()),
+            table: table.map(|t| t.or(DELIMITERS)),
+            pos: 0,
+            batch: [(0, 0); BATCH_LEN],
+            batch_pos: 0,
+            batch_len: 0,
+            base: 0,
+            mask: 0,
+            after_pct_prev: 0,
+            limit: src.len(),
+            err: None,
+            pair_start: 0,
+            eq: None,
+            finished: false,
+        }
+    }
+
+    fn stop_at(&mut self, res: Result<(), ValidateError>) {
+        self.table = None;
+        if let Err(e) = res {
+            self.limit = e.offset();
+            self.err = Some(e);
+        }
+    }
+
+    /// Scans the next block, filling in the bitmask of delimiters.
+    fn scan_block(&mut self) {
+        let (src, i) = (self.src, self.pos);
+        self.base = i;
+
+        let width = self.kernel.width();
+        if self.batch_pos == self.batch_len {
+            self.batch_pos = 0;
+            self.batch_len = match self.kernel {
+                Kernel::Scalar => 0,
+                Kernel::Avx2 => unsafe {
+                    avx2::scan_query(
+                        src,
+                        i,
+                        self.table,
+                        &mut self.after_pct_prev,
+                        &mut self.batch,
+                    )
+                },
+                Kernel::Avx512 => unsafe {
+                    avx512::scan_query(
+                        src,
+                        i,
+                        self.table,
+                        &mut self.after_pct_prev,
+                        &mut self.batch,
+                    )
+                },
+            };
+        }
+        let simd = (self.batch_pos < self.batch_len).then(|| {
+            self.batch_pos += 1;
+            self.batch[self.batch_pos - 1]
+        });
+
+        let mut mask = if let Some((is_delim, is_invalid)) = simd {
+            self.pos += width;
+            if let Some(table) = self.table
+                && is_invalid != 0
+            {
+                let j = i + is_invalid.trailing_zeros() as usize;
+                self.stop_at(crate::fail_at(table, src, j));
+            }
+            // the block scan cannot see a triplet truncated by the end of the string
+            if let Some(table) = self.table
+                && self.pos == src.len()
+                && self.after_pct_prev != 0
+            {
+                self.stop_at(crate::validate_rest(table, src, self.pos));
+            }
+            is_delim
+        } else {
+            let end = src.len().min(i + 64);
+            self.pos = end;
+            if let Some(table) = self.table {
+                self.stop_at(crate::validate_rest(table, src, i));
+            }
+            src[i..end]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &x)| DELIMITERS.allows_ascii(x))
+                .fold(0, |mask, (k, _)| mask | 1 << k)
+        };
+
+        // drop the delimiters at or after the error
+        let n = self.limit.saturating_sub(i);
+        if n < 64 {
+            mask &= (1 << n) - 1;
+        }
+        self.mask = mask;
+    }
+
+    /// Returns the index of the next delimiter.
+    fn next_delim(&mut self) -> Option<usize> {
+        while self.mask == 0 {
+            if self.pos >= self.limit {
+                return None;
+            }
+            self.scan_block();
+        }
+        let k = self.mask.trailing_zeros() as usize;
+        self.mask &= self.mask - 1;
+        Some(self.base + k)
+    }
+
+    /// Ends the current pair at index `end`, returning it if it is not empty.
+    fn take_pair(&mut self, end: usize) -> Option<Spans> {
+        let (start, eq) = (self.pair_start, self.eq.take());
+        self.pair_start = end + 1;
+        if start == end {
+            return None;
+        }
+        Some(match eq {
+            Some(eq) => (start..eq, eq + 1..end),
+            None => (start..end, end..end),
+        })
+    }
+
+    fn next_pair(&mut self) -> Option<Result<Spans, ValidateError>> {
+        loop {
+            let Some(i) = self.next_delim() else {
+                if self.finished {
+                    return None;
+                }
+                self.finished = true;
+                if let Some(e) = self.err {
+                    return Some(Err(e));
+                }
+                return self.take_pair(self.limit).map(Ok);
+            };
+
+            if self.src[i] == b'=' {
+                self.eq.get_or_insert(i);
+            } else if let Some(pair) = self.take_pair(i) {
+                return Some(Ok(pair));
+            }
+        }
+    }
+}
+
+/// An iterator over the key-value pairs of a query string.
+///
+/// This struct is created by [`split_query`].
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct QuerySpans<'a>(Scanner<'a>);
+
+/// An iterator over the key-value pairs of a query string,
+/// validating the string in the same pass.
+///
+/// This struct is created by [`split_query_checked`].
+#[derive(Clone, Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct CheckedQuerySpans<'a>(Scanner<'a>);
+
+/// Splits a query string into key-value pairs.
+///
+/// Pairs are separated by `&` or `;` and empty pairs are skipped.
+/// The key is separated from the value by the first `=` in a pair,
+/// and the value is an empty range at the end of the pair if there is no `=`.
+///
+/// The backend selected for [`validate`](crate::validate) is used,
+/// unless another one is specified with [`QuerySpans::with_backend`].
+pub fn split_query(src: &[u8]) -> QuerySpans<'_> {
+    QuerySpans(Scanner::new(src, None))
+}
+
+/// Splits a query string into key-value pairs like [`split_query`],
+/// validating it with the table in the same pass.
+///
+/// The delimiters are allowed regardless of the table.
+/// On failure, the pairs ending before the error are yielded,
+/// followed by the error.
+pub fn split_query_checked(table: impl naive::Table, src: &[u8]) -> CheckedQuerySpans<'_> {
+    CheckedQuerySpans(Scanner::new(src, Some(table.to_bitset())))
+}
+
+macro_rules! impl_with_backend {
+    ($($ty:ident),+) => {$(
+        impl $ty<'_> {
+            /// Scans the string with the given backend.
+            ///
+            /// Backends without a scanner of their own fall back to the scalar one.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the backend is not supported by the current CPU,
+            /// or if the iteration has started.
+            pub fn with_backend(mut self, backend: Backend) -> Self {
+                assert!(backend.is_supported(), "unsupported backend: {backend}");
+                assert!(self.0.pos == 0, "iteration has started");
+                self.0.kernel = Kernel::new(backend);
+                self
+            }
+        }
+    )+};
+}
+
+impl_with_backend!(QuerySpans, CheckedQuerySpans);
+
+impl Iterator for QuerySpans<'_> {
+    type Item = Spans;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_pair().map(|res| res.unwrap())
+    }
+}
+
+impl Iterator for CheckedQuerySpans<'_> {
+    type Item = Result<Spans, ValidateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_pair()
+    }
+}
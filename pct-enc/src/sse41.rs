@@ -1,23 +1,32 @@
-use crate::naive::table_bitset;
+use crate::{ValidateError, naive};
 use std::arch::x86_64::*;
 
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn validate_3load(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_3load_detailed(table, src).is_ok()
+}
+
 #[rustc_align(64)]
 #[target_feature(enable = "sse4.1")]
-pub unsafe fn validate_3load(src: &[u8]) -> bool {
+pub unsafe fn validate_3load_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
     let mut i = 0;
     if len >= 16 + 2 {
-        if !super::validate_first_two(src) {
-            return false;
+        if !super::first_two_valid(table, src) {
+            return super::fail_in_first_two(table, src);
         }
 
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
+        // the corresponding bit for % is set in this table iff it is allowed
+        let allowed = table.bits();
         let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
 
-        let hexdig = table_bitset::HEXDIG.bits();
+        let hexdig = super::hexdig_after_pct(table).bits();
         let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
 
         let pct = _mm_set1_epi8(b'%' as _);
@@ -62,27 +71,39 @@ pub unsafe fn validate_3load(src: &[u8]) -> bool {
             let is_invalid = _mm_movemask_epi8(is_invalid); // 3 1 1*p0
 
             if is_invalid != 0 {
-                return false;
+                let j = i + 2 + is_invalid.trailing_zeros() as usize;
+                return super::fail_at(table, src, j);
             }
             i += 16;
         }
+        // the loop has validated `src[..i + 2]`
+        i += 2;
     }
-    table_bitset::PATH.validate(&src[i..])
+    super::validate_rest(table, src, i)
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn validate_alignr(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_alignr_detailed(table, src).is_ok()
 }
 
 #[rustc_align(64)]
 #[target_feature(enable = "sse4.1")]
-pub unsafe fn validate_alignr(src: &[u8]) -> bool {
+pub unsafe fn validate_alignr_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
     let mut i = 0;
     if len >= 32 {
-        // the corresponding bit for % is set in this table
-        let allowed = table_bitset::PATH.bits();
+        // the corresponding bit for % is set in this table iff it is allowed
+        let allowed = table.bits();
         let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
 
-        let hexdig = table_bitset::HEXDIG.bits();
+        let hexdig = super::hexdig_after_pct(table).bits();
         let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
 
         let pct = _mm_set1_epi8(b'%' as _);
@@ -123,7 +144,15 @@ pub unsafe fn validate_alignr(src: &[u8]) -> bool {
             let is_invalid = _mm_movemask_epi8(is_invalid); // 3 1 1*p0
 
             if is_invalid != 0 {
-                return false;
+                // bytes following a % are checked to be hexdig above
+                // but not necessarily allowed by the table, so exclude them
+                let is_pct = _mm_movemask_epi8(is_pct) as u16 as u64;
+                let after_pct = super::after_pct_mask(src, i, is_pct);
+                let is_invalid = is_invalid as u16 as u64 & !after_pct;
+                if is_invalid != 0 {
+                    let j = i + is_invalid.trailing_zeros() as usize;
+                    return super::fail_at(table, src, j);
+                }
             }
 
             chunk = next_chunk;
@@ -131,20 +160,29 @@ pub unsafe fn validate_alignr(src: &[u8]) -> bool {
             i += 16;
         }
     }
-    table_bitset::PATH.validate(&src[i..])
+    super::validate_rest(table, src, i)
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn validate_alignl(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_alignl_detailed(table, src).is_ok()
 }
 
 #[rustc_align(64)]
 #[target_feature(enable = "sse4.1")]
-pub unsafe fn validate_alignl(src: &[u8]) -> bool {
+pub unsafe fn validate_alignl_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
-    // the corresponding bit for % is set in this table
-    let allowed = table_bitset::PATH.bits();
+    // the corresponding bit for % is set in this table iff it is allowed
+    let allowed = table.bits();
     let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
 
-    let hexdig = table_bitset::HEXDIG.bits();
+    let hexdig = super::hexdig_after_pct(table).bits();
     let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
 
     let pct = _mm_set1_epi8(b'%' as _);
@@ -185,10 +223,10 @@ pub unsafe fn validate_alignl(src: &[u8]) -> bool {
         let is_invalid = _mm_movemask_epi8(is_invalid); // 3 1 1*p0
 
         if is_invalid != 0 {
-            return false;
+            let j = i + is_invalid.trailing_zeros() as usize;
+            return super::fail_at(table, src, j);
         }
         i += 16;
     }
-    i = i.saturating_sub(2);
-    table_bitset::PATH.validate(&src[i..])
+    super::validate_rest(table, src, i)
 }
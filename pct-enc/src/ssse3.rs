@@ -1,23 +1,32 @@
-use crate::naive::table_bitset;
+use crate::{ValidateError, naive};
 use std::arch::x86_64::*;
 
+#[target_feature(enable = "ssse3")]
+pub unsafe fn validate_3load(table: impl naive::Table, src: &[u8]) -> bool {
+    validate_3load_detailed(table, src).is_ok()
+}
+
 #[rustc_align(64)]
 #[target_feature(enable = "ssse3")]
-pub unsafe fn validate_3load(src: &[u8]) -> bool {
+pub unsafe fn validate_3load_detailed(
+    table: impl naive::Table,
+    src: &[u8],
+) -> Result<(), ValidateError> {
+    let table = table.to_bitset();
     let len = src.len();
     let ptr = src.as_ptr();
 
     let mut i = 0;
     if len >= 16 + 2 {
-        if !super::validate_first_two(src) {
-            return false;
+        if !super::first_two_valid(table, src) {
+            return super::fail_in_first_two(table, src);
         }
 
-        // the corresponding bits for % and hexdig are set in this table
-        let allowed = table_bitset::PATH.bits();
+        // the corresponding bit for % is set in this table iff it is allowed
+        let allowed = table.bits();
         let allowed = _mm_set_epi64x(allowed.1 as _, allowed.0 as _);
 
-        let hexdig = table_bitset::HEXDIG.bits();
+        let hexdig = super::hexdig_after_pct(table).bits();
         let hexdig = _mm_set_epi64x(hexdig.1 as _, hexdig.0 as _);
 
         let pct = _mm_set1_epi8(b'%' as _);
@@ -44,8 +53,10 @@ pub unsafe fn validate_3load(src: &[u8]) -> bool {
             let allowed_per_byte = _mm_shuffle_epi8(allowed, table_idx_per_byte); // 1 0.5 1*p15
             let hexdig_per_byte = _mm_shuffle_epi8(hexdig, table_idx_per_byte); // 1 0.5 1*p15
 
-            let table_per_byte = _mm_andnot_si128(after_pct, allowed_per_byte); // 1 0.33 1*p015
-            let table_per_byte = _mm_or_si128(table_per_byte, hexdig_per_byte); // 1 0.33 1*p015
+            // there is no blendv before SSE4.1, so we blend the tables by hand
+            let allowed_per_byte = _mm_andnot_si128(after_pct, allowed_per_byte); // 1 0.33 1*p015
+            let hexdig_per_byte = _mm_and_si128(after_pct, hexdig_per_byte); // 1 0.33 1*p015
+            let table_per_byte = _mm_or_si128(allowed_per_byte, hexdig_per_byte); // 1 0.33 1*p015
 
             let nz_if_valid = _mm_and_si128(table_per_byte, mask_per_byte); // 1 0.33 1*p015
 
@@ -53,10 +64,13 @@ pub unsafe fn validate_3load(src: &[u8]) -> bool {
             let is_invalid = _mm_movemask_epi8(is_invalid); // 3 1 1*p0
 
             if is_invalid != 0 {
-                return false;
+                let j = i + 2 + is_invalid.trailing_zeros() as usize;
+                return super::fail_at(table, src, j);
             }
             i += 16;
         }
+        // the loop has validated `src[..i + 2]`
+        i += 2;
     }
-    table_bitset::PATH.validate(&src[i..])
+    super::validate_rest(table, src, i)
 }
@@ -0,0 +1,306 @@
+//! Validation and decoding of input arriving in chunks.
+//!
+//! A chunk may end in the middle of a percent-encoded octet or a UTF-8
+//! sequence, in which case its trailing bytes are held back until the next
+//! chunk completes them. Errors are reported with offsets into the whole
+//! stream, as if it were validated with [`validate_detailed`] in one piece.
+
+use crate::{
+    ValidateError, ValidateErrorKind,
+    naive::{self, Decode, DecodedChunk, table_bitset::Table},
+    validate_detailed,
+};
+use std::{
+    io::{self, Read, Write},
+    str,
+};
+
+/// A validator that accepts a string in chunks.
+#[derive(Clone, Debug)]
+pub struct StreamValidator {
+    table: Table,
+    /// The offset of the first byte not yet validated, which is
+    /// the first pending byte if any.
+    offset: usize,
+    /// The bytes of an incomplete octet or sequence ending the previous chunk.
+    pending: [u8; 4],
+    pending_len: usize,
+    err: Option<ValidateError>,
+}
+
+/// Checks whether the error is caused only by the string ending
+/// in the middle of a percent-encoded octet or a UTF-8 sequence.
+fn is_incomplete(s: &[u8], e: ValidateError) -> bool {
+    match e.kind() {
+        ValidateErrorKind::TruncatedTriplet => true,
+        ValidateErrorKind::InvalidUtf8 => str::from_utf8(&s[e.offset()..])
+            .is_err_and(|e| e.valid_up_to() == 0 && e.error_len().is_none()),
+        _ => false,
+    }
+}
+
+impl StreamValidator {
+    /// Creates a validator with the table.
+    #[must_use]
+    pub fn new(table: impl naive::Table) -> Self {
+        Self {
+            table: table.to_bitset(),
+            offset: 0,
+            pending: [0; 4],
+            pending_len: 0,
+            err: None,
+        }
+    }
+
+    /// Returns the number of bytes validated so far, excluding
+    /// those held back.
+    #[must_use]
+    pub fn valid_up_to(&self) -> usize {
+        self.offset
+    }
+
+    /// Validates `s`, which starts at `self.offset`, holding back its trailing
+    /// bytes if they are incomplete. Returns the number of bytes validated.
+    fn check(&mut self, s: &[u8]) -> Result<usize, ValidateError> {
+        match validate_detailed(self.table, s) {
+            Ok(()) => {
+                self.offset += s.len();
+                Ok(s.len())
+            }
+            Err(e) if is_incomplete(s, e) => {
+                let rest = &s[e.offset()..];
+                self.pending[..rest.len()].copy_from_slice(rest);
+                self.pending_len = rest.len();
+                self.offset += e.offset();
+                Ok(e.offset())
+            }
+            Err(e) => {
+                let e = e.shift(self.offset);
+                self.err = Some(e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Validates the next chunk, returning the octet or sequence completed
+    /// by its first bytes if any was pending, and the rest of the chunk
+    /// that is validated.
+    fn feed<'c>(
+        &mut self,
+        mut chunk: &'c [u8],
+    ) -> Result<([u8; 4], usize, &'c [u8]), ValidateError> {
+        if let Some(e) = self.err {
+            return Err(e);
+        }
+
+        let (mut head, mut head_len) = ([0; 4], 0);
+        if self.pending_len != 0 {
+            let need = match self.pending[0] {
+                b'%' => 3,
+                x => x.leading_ones() as usize,
+            };
+            let n = (need - self.pending_len).min(chunk.len());
+            let (taken, rest) = chunk.split_at(n);
+            chunk = rest;
+
+            head = self.pending;
+            head[self.pending_len..self.pending_len + n].copy_from_slice(taken);
+            let len = self.pending_len + n;
+            self.pending_len = 0;
+            head_len = self.check(&head[..len])?;
+        }
+
+        let len = self.check(chunk)?;
+        Ok((head, head_len, &chunk[..len]))
+    }
+
+    /// Validates the next chunk of the string.
+    ///
+    /// Once an error is returned, it is returned again by every later call.
+    pub fn update(&mut self, chunk: &[u8]) -> Result<(), ValidateError> {
+        self.feed(chunk).map(|_| ())
+    }
+
+    /// Checks that the string does not end in the middle of
+    /// a percent-encoded octet or a UTF-8 sequence.
+    ///
+    /// Returns the first error found if any.
+    pub fn finish(&self) -> Result<(), ValidateError> {
+        if let Some(e) = self.err {
+            return Err(e);
+        }
+        let pending = &self.pending[..self.pending_len];
+        self.table
+            .validate_detailed(pending)
+            .map_err(|e| e.shift(self.offset))
+    }
+}
+
+/// Validates the bytes written, so that a reader can be validated
+/// with [`io::copy`].
+///
+/// Validation errors are returned as [`io::ErrorKind::InvalidData`].
+/// Call [`StreamValidator::finish`] when all is written.
+impl Write for StreamValidator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf).map_err(invalid_data)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn invalid_data(e: ValidateError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// A decoder that validates and decodes a string in chunks.
+#[derive(Clone, Debug)]
+pub struct StreamDecoder {
+    validator: StreamValidator,
+}
+
+impl StreamDecoder {
+    /// Creates a decoder that validates with the table.
+    #[must_use]
+    pub fn new(table: impl naive::Table) -> Self {
+        Self {
+            validator: StreamValidator::new(table),
+        }
+    }
+
+    /// Validates the next chunk of the string and appends the decoded bytes
+    /// to `dst`.
+    ///
+    /// On failure, nothing is appended for the chunk, and the error
+    /// is returned again by every later call.
+    pub fn decode(&mut self, chunk: &[u8], dst: &mut Vec<u8>) -> Result<(), ValidateError> {
+        let (head, head_len, body) = self.validator.feed(chunk)?;
+        for s in [&head[..head_len], body] {
+            for chunk in Decode::new(s) {
+                match chunk {
+                    DecodedChunk::Unencoded(s) => dst.extend_from_slice(s),
+                    DecodedChunk::PctDecoded(x) => dst.push(x),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the string does not end in the middle of
+    /// a percent-encoded octet or a UTF-8 sequence.
+    ///
+    /// Returns the first error found if any.
+    pub fn finish(&self) -> Result<(), ValidateError> {
+        self.validator.finish()
+    }
+}
+
+/// A reader that validates and decodes the bytes read from another reader.
+///
+/// Validation errors are returned as [`io::ErrorKind::InvalidData`],
+/// including those found at the end of the input.
+#[derive(Debug)]
+pub struct DecodeReader<R> {
+    inner: R,
+    decoder: StreamDecoder,
+    src: Box<[u8]>,
+    decoded: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    const BUF_SIZE: usize = 8 * 1024;
+
+    /// Creates a reader that validates with the table.
+    pub fn new(table: impl naive::Table, inner: R) -> Self {
+        Self {
+            inner,
+            decoder: StreamDecoder::new(table),
+            src: vec![0; Self::BUF_SIZE].into_boxed_slice(),
+            decoded: Vec::with_capacity(Self::BUF_SIZE),
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.eof || buf.is_empty() {
+                return Ok(0);
+            }
+            self.decoded.clear();
+            self.pos = 0;
+
+            let n = self.inner.read(&mut self.src)?;
+            if n == 0 {
+                self.eof = true;
+                self.decoder.finish().map_err(invalid_data)?;
+            } else {
+                let res = self.decoder.decode(&self.src[..n], &mut self.decoded);
+                res.map_err(invalid_data)?;
+            }
+        }
+
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A writer that validates and decodes the bytes written to it
+/// before writing them to another writer.
+///
+/// Validation errors are returned as [`io::ErrorKind::InvalidData`].
+/// Call [`DecodeWriter::finish`] when all is written.
+#[derive(Debug)]
+pub struct DecodeWriter<W> {
+    inner: W,
+    decoder: StreamDecoder,
+    decoded: Vec<u8>,
+}
+
+impl<W: Write> DecodeWriter<W> {
+    /// Creates a writer that validates with the table.
+    pub fn new(table: impl naive::Table, inner: W) -> Self {
+        Self {
+            inner,
+            decoder: StreamDecoder::new(table),
+            decoded: Vec::new(),
+        }
+    }
+
+    /// Checks that the input did not end in the middle of a percent-encoded
+    /// octet or a UTF-8 sequence, and flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.decoder.finish().map_err(invalid_data)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for DecodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.decoded.clear();
+        self.decoder
+            .decode(buf, &mut self.decoded)
+            .map_err(invalid_data)?;
+        self.inner.write_all(&self.decoded)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,271 @@
+use std::{fs, io};
+
+use pct_enc::{naive::Decode, *};
+
+const FNS: [unsafe fn(&[u8], *mut u8) -> usize; 2] = [avx2::decode, avx512::decode];
+
+fn decode_with(f: unsafe fn(&[u8], *mut u8) -> usize, src: &[u8]) -> Vec<u8> {
+    let mut dst = Vec::with_capacity(src.len());
+    unsafe {
+        let len = f(src, dst.as_mut_ptr());
+        dst.set_len(len);
+    }
+    dst
+}
+
+#[test]
+fn test_decode() -> io::Result<()> {
+    let src = fs::read("enc.txt")?;
+    let expected = fs::read("raw.bin")?;
+
+    assert_eq!(Decode::new(&src).to_bytes(), &expected[..]);
+
+    for (i, f) in FNS.into_iter().enumerate() {
+        assert!(decode_with(f, &src) == expected, "failed on {i}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_decode_lengths() {
+    let inputs = [
+        b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA"
+            .repeat(3),
+        b"%41%62".repeat(40),
+        b"a%41".repeat(60),
+        b"ab%41".repeat(60),
+        b"abcdefgh".repeat(30),
+    ];
+
+    for src in &inputs {
+        for start in 0..3 {
+            for end in start..=src.len() {
+                let src = &src[start..end];
+                // skip the slices that cut a triplet
+                if !naive::table_bitset::PATH.validate(src) {
+                    continue;
+                }
+                let expected = Decode::new(src).to_bytes();
+                for (i, f) in FNS.into_iter().enumerate() {
+                    assert_eq!(
+                        decode_with(f, src),
+                        *expected,
+                        "failed on {i} with {start}..{end}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_checked() {
+    use naive::table_bitset::*;
+    use std::borrow::Cow;
+
+    let tables = [PATH, UNRESERVED, DIGIT.or_pct_encoded(), IPATH];
+    let inputs = [
+        b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA"
+            .repeat(3),
+        [&b"0123456789".repeat(20)[..], b"%4a%"].concat(),
+        [&b"0123456789".repeat(20)[..], b"%4G"].concat(),
+        [&b"0123456789".repeat(20)[..], b"\xff"].concat(),
+        b"abcdefgh".repeat(30),
+        "%41é%42日本".repeat(30).into_bytes(),
+        [&"日本".repeat(20).into_bytes()[..], b"\xe2\x82%41"].concat(),
+    ];
+
+    for table in tables {
+        for src in &inputs {
+            for end in 0..=src.len() {
+                let src = &src[..end];
+                let expected = naive::decode_checked(table, src);
+                let res = unsafe { avx512::decode_checked(table, src) };
+                assert_eq!(res, expected, "failed with len {end}");
+                assert_eq!(decode_checked(table, src), expected);
+                for backend in dispatch::Backend::ALL
+                    .into_iter()
+                    .filter(|b| b.is_supported())
+                {
+                    assert_eq!(
+                        backend.decode_checked(table, src),
+                        expected,
+                        "{backend} failed"
+                    );
+                }
+
+                if let Ok(s) = res {
+                    assert_eq!(matches!(s, Cow::Borrowed(_)), !src.contains(&b'%'));
+                }
+            }
+        }
+    }
+
+    let src = fs::read("enc.txt").unwrap();
+    let expected = fs::read("raw.bin").unwrap();
+    assert!(unsafe { avx512::decode_checked(PATH, &src) }.unwrap() == expected);
+}
+
+#[test]
+fn test_decode_to_str() {
+    use std::{borrow::Cow, str};
+
+    let cases: [&[u8]; 10] = [
+        b"",
+        b"abc",
+        "Stra\u{df}e/\u{65e5}\u{672c}".as_bytes(),
+        b"%C3%9F%E6%97%A5/%F0%9F%98%80",
+        b"%C3",
+        b"%C3%28",
+        b"%C0%80",
+        b"%ED%A0%80",
+        b"\xff%41",
+        b"\xc3",
+    ];
+    // short and long, valid and invalid sequences, placed at every offset
+    // so that they straddle the blocks
+    let seqs: [&[u8]; 8] = [
+        b"%C3%A9",
+        "\u{e9}".as_bytes(),
+        b"%F0%9F%98%80",
+        b"%E2%82",
+        b"%80",
+        b"\xf4\x90%80%80",
+        b"%E0%9F%BF",
+        b"%F0%9F%98",
+    ];
+    let mut inputs: Vec<Vec<u8>> = cases.iter().map(|s| s.to_vec()).collect();
+    for seq in seqs {
+        for pad in [&b"a"[..], b"%41"] {
+            for n in 0..80 {
+                let mut src = pad.repeat(n);
+                src.extend_from_slice(seq);
+                src.extend_from_slice(&b"%2F\xce\xa9".repeat(30));
+                inputs.push(src.clone());
+                src.extend_from_slice(seq);
+                inputs.push(src);
+            }
+        }
+    }
+
+    for src in &inputs {
+        let bytes = Decode::new(src).to_bytes();
+        let expected = str::from_utf8(&bytes);
+
+        let res = Decode::new(src).to_str();
+        assert_eq!(
+            res.as_deref().map_err(|e| *e),
+            expected,
+            "failed on {src:?}"
+        );
+        if let Ok(s) = res {
+            assert_eq!(matches!(s, Cow::Borrowed(_)), !src.contains(&b'%'));
+        }
+
+        let lossy = Decode::new(src).to_string_lossy();
+        assert_eq!(lossy, String::from_utf8_lossy(&bytes));
+        assert_eq!(
+            matches!(lossy, Cow::Borrowed(_)),
+            expected.is_ok() && !src.contains(&b'%')
+        );
+
+        unsafe {
+            assert_eq!(avx512::validate_utf8(&bytes), expected.is_ok());
+            let mut dst = Vec::with_capacity(src.len());
+            let (len, valid) = avx512::decode_utf8(src, dst.as_mut_ptr()).unwrap();
+            dst.set_len(len);
+            assert_eq!(dst, *bytes);
+            assert_eq!(valid, expected.is_ok(), "failed on {src:?}");
+        }
+    }
+}
+
+#[test]
+fn test_decode_utf8_malformed() {
+    // non-ASCII bytes that would be looked up as hexdig modulo 128
+    let malformed: [&[u8]; 5] = [b"%4", b"%zz", b"%4g", b"%%41", b"%\xb0\xc1"];
+    for pattern in malformed {
+        for i in 0..150 {
+            let mut src = b"a".repeat(i);
+            src.extend_from_slice(pattern);
+            src.extend_from_slice(&b"%41".repeat(40));
+            for src in [&src[..], &src[..i + pattern.len()]] {
+                let mut dst = Vec::<u8>::with_capacity(src.len());
+                let res = unsafe { avx512::decode_utf8(src, dst.as_mut_ptr()) };
+                assert_eq!(res, None, "failed on {src:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_in_place() {
+    fn decode_lenient(src: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < src.len() {
+            match src[i..] {
+                [b'%', hi, lo, ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                    let hex = std::str::from_utf8(&src[i + 1..i + 3]).unwrap();
+                    out.push(u8::from_str_radix(hex, 16).unwrap());
+                    i += 3;
+                }
+                _ => {
+                    out.push(src[i]);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+
+    type DecodeInPlaceFn = fn(&mut [u8]) -> usize;
+
+    let fns: [(&str, DecodeInPlaceFn); 3] = [
+        ("naive", naive::decode_in_place),
+        ("avx512", |buf| unsafe { avx512::decode_in_place(buf) }),
+        ("dispatch", decode_in_place),
+    ];
+
+    let inputs = [
+        b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA"
+            .repeat(3),
+        b"%41%62".repeat(40),
+        b"a%41".repeat(60),
+        b"abcdefgh".repeat(30),
+        // invalid triplets are left as is
+        b"%%4%4g%%41%".repeat(20),
+        "%C3%A9\u{e9}%zz".repeat(20).into_bytes(),
+    ];
+
+    for src in &inputs {
+        for start in 0..3 {
+            for end in start..=src.len() {
+                let src = &src[start..end];
+                let expected = decode_lenient(src);
+                for (name, f) in fns {
+                    let mut buf = src.to_vec();
+                    let len = f(&mut buf);
+                    assert_eq!(buf[..len], expected, "{name} failed with {start}..{end}");
+                }
+                for backend in dispatch::Backend::ALL
+                    .into_iter()
+                    .filter(|b| b.is_supported())
+                {
+                    let mut buf = src.to_vec();
+                    let len = backend.decode_in_place(&mut buf);
+                    assert_eq!(buf[..len], expected, "{backend} failed with {start}..{end}");
+                }
+            }
+        }
+    }
+
+    let mut buf = b"a%20b%2".to_vec();
+    decode_vec_in_place(&mut buf);
+    assert_eq!(buf, b"a b%2");
+
+    let s = decode_string_in_place("%C3%A9t%C3%A9".to_owned());
+    assert_eq!(s.unwrap(), "\u{e9}t\u{e9}");
+    let e = decode_string_in_place("%C3%28".to_owned()).unwrap_err();
+    assert_eq!(e.into_bytes(), b"\xc3(");
+}
@@ -0,0 +1,32 @@
+// The backend is process-global, so it is tested in a binary of its own,
+// and in a single test so that the steps do not race.
+
+use pct_enc::{
+    dispatch::{self, Backend},
+    naive::table_bitset::*,
+    *,
+};
+use std::env;
+
+#[test]
+fn test_backend() {
+    // an unknown backend falls back to the detected one instead of panicking
+    unsafe { env::set_var(dispatch::BACKEND_ENV_VAR, "bogus") };
+    assert!(validate(PATH, b"/a%20b"));
+    assert!(!validate(UNRESERVED, b"/a%20b"));
+    assert_eq!(dispatch::backend(), Backend::detect());
+
+    let previous = dispatch::backend();
+    for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+        dispatch::set_backend(backend);
+        assert_eq!(dispatch::backend(), backend);
+        assert!(validate(PATH, b"/a%20b"), "{backend} failed");
+        assert_eq!(
+            validate_detailed(UNRESERVED, b"/a%20b").map_err(|e| e.offset()),
+            Err(0),
+            "{backend} failed"
+        );
+    }
+    dispatch::set_backend(previous);
+    assert_eq!(dispatch::backend(), previous);
+}
@@ -0,0 +1,166 @@
+use std::{fs, io};
+
+use pct_enc::{
+    dispatch::Backend,
+    naive::{Encode, HexCase, table_bitset::*},
+    *,
+};
+
+type EncodeFn = unsafe fn(Table, &[u8], &mut Vec<u8>);
+
+const FNS: [EncodeFn; 2] = [avx2::encode_into, avx512::encode_into];
+
+fn encode_naive(table: Table, src: &[u8]) -> Vec<u8> {
+    let mut dst = Vec::new();
+    for chunk in Encode::new(table, src) {
+        dst.extend_from_slice(chunk.as_bytes());
+    }
+    dst
+}
+
+#[test]
+fn test_encode() -> io::Result<()> {
+    let src = fs::read("raw.bin")?;
+    let expected = fs::read("enc.txt")?;
+
+    assert!(encode_naive(PATH, &src) == expected);
+
+    for (i, f) in FNS.into_iter().enumerate() {
+        let mut dst = b"prefix".to_vec();
+        unsafe { f(PATH, &src, &mut dst) };
+        assert!(dst[..6] == *b"prefix", "failed on {i}");
+        assert!(dst[6..] == expected, "failed on {i}");
+    }
+
+    let mut dst = b"prefix".to_vec();
+    encode_into(PATH, &src, &mut dst);
+    assert!(dst[6..] == expected);
+    Ok(())
+}
+
+#[test]
+fn test_encode_tables() {
+    let tables = [PATH, UNRESERVED, SUB_DELIMS, DIGIT.or_pct_encoded()];
+    let src: Vec<u8> = (0..=255)
+        .chain((0..=255).rev())
+        .chain(b"a%b c".repeat(20))
+        .collect();
+
+    for table in tables {
+        for start in 0..3 {
+            for end in start..=src.len() {
+                let src = &src[start..end];
+                let expected = encode_naive(table, src);
+                for (i, f) in FNS.into_iter().enumerate() {
+                    let mut dst = Vec::new();
+                    unsafe { f(table, src, &mut dst) };
+                    assert_eq!(dst, expected, "failed on {i} with {start}..{end}");
+                }
+                for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+                    let mut dst = Vec::new();
+                    backend.encode_into(table, src, &mut dst);
+                    assert_eq!(dst, expected, "{backend} failed with {start}..{end}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_encode_preserve_pct_encoded() {
+    let encode_preserving = |table: Table, src: &[u8]| {
+        let mut dst = Vec::new();
+        for chunk in Encode::new(table, src).preserve_pct_encoded() {
+            dst.extend_from_slice(chunk.as_bytes());
+        }
+        dst
+    };
+
+    let src = b"/a b/%20%2 %%41%zz%4a%";
+    assert_eq!(
+        encode_preserving(PATH, src),
+        b"/a%20b/%20%252%20%25%41%25zz%4a%25"
+    );
+    assert_eq!(
+        encode_naive(PATH, src),
+        b"/a%20b/%2520%252%20%25%2541%25zz%254a%25"
+    );
+    // the hex digits of a triplet need not be allowed by the table
+    assert_eq!(encode_preserving(DIGIT, b"%4a4a%"), b"%4a4%61%25");
+
+    let src: Vec<u8> = (0..=255).chain(b"%41%4%%a%ZZ%e9".repeat(5)).collect();
+    for table in [PATH, UNRESERVED, DIGIT.or_pct_encoded()] {
+        for start in 0..3 {
+            for end in start..=src.len() {
+                let src = &src[start..end];
+                let res = encode_preserving(table, src);
+                assert!(table.or_pct_encoded().validate(&res));
+                // encoding again changes nothing
+                assert_eq!(encode_preserving(table, &res), res);
+                // nor does encoding what is already encoded
+                let encoded = encode_naive(table, src);
+                assert_eq!(encode_preserving(table, &encoded), encoded);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_encode_hex_case() {
+    type EncodeWithCaseFn = unsafe fn(Table, &[u8], &mut Vec<u8>, HexCase);
+
+    let fns: [EncodeWithCaseFn; 2] = [avx2::encode_into_with_case, avx512::encode_into_with_case];
+    let encode_lower = |table: Table, src: &[u8]| {
+        let mut dst = Vec::new();
+        for &x in src {
+            if table.contains(x) {
+                dst.push(x);
+            } else {
+                dst.extend_from_slice(format!("%{x:02x}").as_bytes());
+            }
+        }
+        dst
+    };
+
+    let res: Vec<u8> = Encode::new(PATH, "a b%/\u{e9}".as_bytes())
+        .hex_case(HexCase::Lower)
+        .flat_map(|chunk| chunk.as_bytes().to_vec())
+        .collect();
+    assert_eq!(res, b"a%20b%25/%c3%a9");
+    // preserved triplets keep their case
+    let res: Vec<u8> = Encode::new(PATH, b"%4A%4a\xff")
+        .preserve_pct_encoded()
+        .hex_case(HexCase::Lower)
+        .flat_map(|chunk| chunk.as_bytes().to_vec())
+        .collect();
+    assert_eq!(res, b"%4A%4a%ff");
+    let mut dst = Vec::new();
+    encode_into_with_case(PATH, "a b%/\u{e9}".as_bytes(), &mut dst, HexCase::Lower);
+    assert_eq!(dst, b"a%20b%25/%c3%a9");
+
+    let src: Vec<u8> = (0..=255).chain(b"a%b cZ".repeat(20)).collect();
+    for table in [PATH, UNRESERVED, DIGIT.or_pct_encoded()] {
+        for start in 0..3 {
+            for end in start..=src.len() {
+                let src = &src[start..end];
+                let expected = encode_lower(table, src);
+                let res: Vec<u8> = Encode::new(table, src)
+                    .hex_case(HexCase::Lower)
+                    .flat_map(|chunk| chunk.as_bytes().to_vec())
+                    .collect();
+                assert_eq!(res, expected, "naive failed with {start}..{end}");
+
+                for (i, f) in fns.into_iter().enumerate() {
+                    let mut dst = Vec::new();
+                    unsafe { f(table, src, &mut dst, HexCase::Lower) };
+                    assert_eq!(dst, expected, "failed on {i} with {start}..{end}");
+                }
+                for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+                    let mut dst = Vec::new();
+                    backend.encode_into_with_case(table, src, &mut dst, HexCase::Lower);
+                    assert_eq!(dst, expected, "{backend} failed with {start}..{end}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use pct_enc::{
+    ValidateErrorKind,
+    naive::{EStr, EString, table_bitset::*},
+};
+
+#[test]
+fn test_estr() {
+    let s = EStr::new(QUERY, b"a=%41&b=%E2%82%AC&&c").unwrap();
+    assert_eq!(s.as_bytes(), b"a=%41&b=%E2%82%AC&&c");
+    assert_eq!(s.len(), 20);
+    assert_eq!(s.decode().to_bytes(), "a=A&b=€&&c".as_bytes());
+
+    let parts: Vec<&[u8]> = s.split(b'&').map(EStr::as_bytes).collect();
+    assert_eq!(parts, [&b"a=%41"[..], b"b=%E2%82%AC", b"", b"c"]);
+    assert_eq!(EStr::EMPTY.split(b'&').count(), 1);
+
+    let (k, v) = parts_of(s.split(b'&').nth(1).unwrap());
+    assert_eq!((&k[..], &v[..]), (&b"b"[..], "€".as_bytes()));
+    assert!(s.split_once(b'#').is_none());
+
+    let err = EStr::new(PATH, b"a%4").unwrap_err();
+    assert_eq!(
+        (err.offset(), err.kind()),
+        (1, ValidateErrorKind::TruncatedTriplet)
+    );
+    assert!(EStr::new(IPATH, "/日本".as_bytes()).is_ok());
+}
+
+fn parts_of(s: &EStr) -> (Vec<u8>, Vec<u8>) {
+    let (k, v) = s.split_once(b'=').unwrap();
+    (k.decode().to_bytes().into(), v.decode().to_bytes().into())
+}
+
+#[test]
+#[should_panic = "splitting with non-reserved character"]
+fn test_estr_split_panic() {
+    let _ = EStr::new(PATH, b"%41").unwrap().split(b'A');
+}
+
+#[test]
+fn test_estring() {
+    let mut buf = EString::new(QUERY);
+    buf.encode(b"a b");
+    buf.push_estr(EStr::new(QUERY, b"=%41&").unwrap()).unwrap();
+    buf.encode("€/?#".as_bytes());
+    assert_eq!(buf.as_bytes(), b"a%20b=%41&%E2%82%AC/?%23");
+    assert!(QUERY.validate(buf.as_bytes()));
+    assert_eq!(buf.decode().to_bytes(), "a b=A&€/?#".as_bytes());
+
+    // valid against PATH but not against UNRESERVED
+    let mut buf = EString::new(UNRESERVED);
+    let err = buf.push_estr(EStr::new(PATH, b"a/b").unwrap()).unwrap_err();
+    assert_eq!(
+        (err.offset(), err.kind()),
+        (1, ValidateErrorKind::DisallowedByte)
+    );
+    assert!(buf.is_empty());
+
+    let mut buf = EString::new(IPATH);
+    buf.encode("/日本 語".as_bytes());
+    assert_eq!(buf.into_bytes(), b"/%E6%97%A5%E6%9C%AC%20%E8%AA%9E");
+}
+
+#[test]
+#[should_panic = "table does not allow percent-encoded octets"]
+fn test_estring_encode_panic() {
+    EString::new(UNRESERVED).encode(b" ");
+}
@@ -0,0 +1,60 @@
+use pct_enc::naive::{FormDecode, FormEncode, FormPairs};
+use std::borrow::Cow;
+
+#[test]
+fn test_form_encode() {
+    let cases: [(&[u8], &[u8]); 5] = [
+        (b"", b""),
+        (b"az-AZ_09.*", b"az-AZ_09.*"),
+        (b"a b+c", b"a+b%2Bc"),
+        ("~!€ ".as_bytes(), b"%7E%21%E2%82%AC+"),
+        (b"  ", b"++"),
+    ];
+
+    for (src, expected) in cases {
+        let res = FormEncode::new(src).to_bytes();
+        assert_eq!(res, expected);
+        assert_eq!(matches!(res, Cow::Borrowed(_)), src == expected);
+        assert_eq!(FormDecode::new(expected).to_bytes(), src);
+    }
+}
+
+#[test]
+fn test_form_decode() {
+    let cases: [(&[u8], &[u8]); 7] = [
+        (b"", b""),
+        (b"abc", b"abc"),
+        (b"a+b%2Bc", b"a b+c"),
+        (b"%E2%82%ac+", "€ ".as_bytes()),
+        // invalid triplets are left as is
+        (b"%%4%4g%", b"%%4%4g%"),
+        (b"%%41", b"%A"),
+        (b"++%", b"  %"),
+    ];
+
+    for (src, expected) in cases {
+        let res = FormDecode::new(src).to_bytes();
+        assert_eq!(res, expected);
+        assert_eq!(matches!(res, Cow::Borrowed(_)), src == expected);
+    }
+}
+
+#[test]
+fn test_form_pairs() {
+    let src = b"&a=1&&b=x+y%3D%26z&c&=d&e==&%E2%82%AC=%";
+    let pairs: Vec<_> = FormPairs::new(src).collect();
+    let expected: [(&[u8], &[u8]); 6] = [
+        (b"a", b"1"),
+        (b"b", b"x y=&z"),
+        (b"c", b""),
+        (b"", b"d"),
+        (b"e", b"="),
+        ("€".as_bytes(), b"%"),
+    ];
+    assert_eq!(pairs.len(), expected.len());
+    for ((name, value), (en, ev)) in pairs.iter().zip(expected) {
+        assert_eq!((&name[..], &value[..]), (en, ev));
+    }
+    assert!(matches!(pairs[0], (Cow::Borrowed(_), Cow::Borrowed(_))));
+    assert_eq!(FormPairs::new(b"&&").count(), 0);
+}
@@ -0,0 +1,66 @@
+use pct_enc::naive::{iri_to_uri, table_bitset::*, uri_to_iri};
+use std::borrow::Cow;
+
+#[test]
+fn test_iri_to_uri() {
+    let cases: [(&str, &str); 4] = [
+        ("http://example.org/", "http://example.org/"),
+        (
+            "http://r\u{e9}sum\u{e9}.example.org",
+            "http://r%C3%A9sum%C3%A9.example.org",
+        ),
+        (
+            "/D\u{fc}rst%20%C3%BC?q=\u{1f600}",
+            "/D%C3%BCrst%20%C3%BC?q=%F0%9F%98%80",
+        ),
+        ("%\u{e9}%", "%%C3%A9%"),
+    ];
+
+    for (iri, uri) in cases {
+        let res = iri_to_uri(iri.as_bytes());
+        assert_eq!(res, uri.as_bytes());
+        assert_eq!(matches!(res, Cow::Borrowed(_)), iri.is_ascii());
+    }
+
+    let iri = "/wiki/Straße/日本語?q=\u{e000}";
+    assert!(IQUERY.validate(iri.as_bytes()));
+    assert!(QUERY.validate(&iri_to_uri(iri.as_bytes())));
+}
+
+#[test]
+fn test_uri_to_iri() {
+    let cases: [(&str, &str); 9] = [
+        (
+            "http://www.example.org/D%C3%BCrst",
+            "http://www.example.org/D\u{fc}rst",
+        ),
+        // not UTF-8
+        (
+            "http://www.example.org/D%FCrst",
+            "http://www.example.org/D%FCrst",
+        ),
+        // a bidirectional formatting character
+        (
+            "http://xn--99zt52a.example.org/%e2%80%ae",
+            "http://xn--99zt52a.example.org/%e2%80%ae",
+        ),
+        // ASCII is left encoded
+        ("%41%2F%25", "%41%2F%25"),
+        ("%f0%9f%98%80%C3%A9%C3", "\u{1f600}\u{e9}%C3"),
+        // overlong and truncated sequences
+        ("%C0%AF%E2%82", "%C0%AF%E2%82"),
+        // not ucschar
+        ("%C2%80%EE%80%80%EF%B7%90", "%C2%80%EE%80%80%EF%B7%90"),
+        ("%C3%A", "%C3%A"),
+        ("a%%C3%A9%", "a%\u{e9}%"),
+    ];
+
+    for (uri, iri) in cases {
+        let res = uri_to_iri(uri.as_bytes());
+        assert_eq!(res, iri.as_bytes(), "{uri}");
+        assert_eq!(matches!(res, Cow::Borrowed(_)), uri == iri);
+    }
+
+    let iri = "/wiki/Straße/日本語/😀";
+    assert_eq!(uri_to_iri(&iri_to_uri(iri.as_bytes())), iri.as_bytes());
+}
@@ -0,0 +1,72 @@
+use pct_enc::{
+    dispatch::Backend,
+    naive::{self, table_bitset::*},
+    *,
+};
+use std::borrow::Cow;
+
+#[test]
+fn test_normalize() {
+    let cases: [(&[u8], &[u8]); 8] = [
+        (b"/%7euser/%2fa%41", b"/~user/%2FaA"),
+        (b"%e2%82%ac%E2%82%AC", b"%E2%82%AC%E2%82%AC"),
+        (b"%25%7E%7e", b"%25~~"),
+        (b"%4%%4g%a", b"%4%%4g%a"),
+        (b"%%7e%", b"%~%"),
+        (b"%C3\xc3%a9", b"%C3\xc3%A9"),
+        (b"a-b_c", b"a-b_c"),
+        (b"", b""),
+    ];
+
+    for (src, expected) in cases {
+        let res = naive::normalize(UNRESERVED, src);
+        assert_eq!(res, expected);
+        assert_eq!(matches!(res, Cow::Borrowed(_)), src == expected);
+        assert_eq!(normalize(UNRESERVED, src), expected);
+    }
+
+    // equivalent URLs compare equal after normalization
+    // delimiters allowed by the table are decoded too
+    assert_eq!(normalize(PATH, b"/a%2Fb%3f"), &b"/a/b%3F"[..]);
+    assert_eq!(normalize(UNRESERVED, b"/a%2fb"), &b"/a%2Fb"[..]);
+
+    let a = normalize(UNRESERVED, b"/%7Efoo/%e2%82%ACbar");
+    let b = normalize(UNRESERVED, b"/~foo/%E2%82%acbar");
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_normalize_simd() {
+    let tables = [UNRESERVED, PATH, DIGIT, IPATH, Table::new(b"")];
+    let inputs = [
+        b"%3c%88,Kh%9C%3e%90%3F@%bb%B4%e8%96%18%9F%3C%5c%93@%1D%cd%25%13%3f%99%1CP%FA%88%ea"
+            .repeat(3),
+        b"%7e%41%61%5F%2d%30".repeat(20),
+        b"abcdefgh".repeat(30),
+        b"%4%%4g%a%%7e%".repeat(20),
+        [&b"0123456789".repeat(20)[..], b"%e2\xc3%a9%7e"].concat(),
+        "日本%e6%97%a5%7E".repeat(20).into_bytes(),
+    ];
+
+    for table in tables {
+        for src in &inputs {
+            for start in 0..3 {
+                for end in start..=src.len() {
+                    let src = &src[start..end];
+                    let expected = naive::normalize(table, src);
+                    let res = unsafe { avx512::normalize(table, src) };
+                    assert_eq!(res, expected, "failed with {start}..{end}");
+                    for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+                        let res = backend.normalize(table, src);
+                        assert_eq!(res, expected, "{backend} failed with {start}..{end}");
+                    }
+                    assert_eq!(
+                        matches!(res, Cow::Borrowed(_)),
+                        matches!(expected, Cow::Borrowed(_)),
+                        "failed with {start}..{end}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use pct_enc::{
+    naive::{Decode, Encode, table_bitset::*},
+    *,
+};
+
+type ValidateFn = unsafe fn(Table, &[u8]) -> bool;
+type DetailedFn = unsafe fn(Table, &[u8]) -> Result<(), ValidateError>;
+type EncodeFn = unsafe fn(Table, &[u8], &mut Vec<u8>);
+type DecodeFn = unsafe fn(&[u8], *mut u8) -> usize;
+type DecodeInPlaceFn = unsafe fn(&mut [u8]) -> usize;
+
+const VALIDATE_FNS: [(&str, ValidateFn, DetailedFn); 13] = [
+    (
+        "ssse3_3load",
+        ssse3::validate_3load,
+        ssse3::validate_3load_detailed,
+    ),
+    (
+        "sse41_3load",
+        sse41::validate_3load,
+        sse41::validate_3load_detailed,
+    ),
+    (
+        "sse41_alignr",
+        sse41::validate_alignr,
+        sse41::validate_alignr_detailed,
+    ),
+    (
+        "sse41_alignl",
+        sse41::validate_alignl,
+        sse41::validate_alignl_detailed,
+    ),
+    (
+        "avx2_3load",
+        avx2::validate_3load,
+        avx2::validate_3load_detailed,
+    ),
+    (
+        "avx2_alignr",
+        avx2::validate_alignr,
+        avx2::validate_alignr_detailed,
+    ),
+    (
+        "avx2_alignl",
+        avx2::validate_alignl,
+        avx2::validate_alignl_detailed,
+    ),
+    (
+        "avx512_3load",
+        avx512::validate_3load,
+        avx512::validate_3load_detailed,
+    ),
+    (
+        "avx512_3load_gf2p8affine",
+        avx512::validate_3load_gf2p8affine,
+        avx512::validate_3load_gf2p8affine_detailed,
+    ),
+    (
+        "avx512_3load_perm",
+        avx512::validate_3load_perm,
+        avx512::validate_3load_perm_detailed,
+    ),
+    (
+        "avx512_alignr",
+        avx512::validate_alignr,
+        avx512::validate_alignr_detailed,
+    ),
+    (
+        "avx512_alignl",
+        avx512::validate_alignl,
+        avx512::validate_alignl_detailed,
+    ),
+    (
+        "avx512_iri",
+        avx512::validate_iri,
+        avx512::validate_iri_detailed,
+    ),
+];
+
+const ENCODE_FNS: [(&str, EncodeFn); 3] = [
+    ("avx2", avx2::encode_into),
+    ("avx512", avx512::encode_into),
+    ("dispatch", |table, src, dst| encode_into(table, src, dst)),
+];
+
+const DECODE_FNS: [(&str, DecodeFn); 2] = [("avx2", avx2::decode), ("avx512", avx512::decode)];
+
+const DECODE_IN_PLACE_FNS: [(&str, DecodeInPlaceFn); 2] = [
+    ("avx512", avx512::decode_in_place),
+    ("dispatch", |buf| decode_in_place(buf)),
+];
+
+/// Inserted at every position close to a 16-byte boundary.
+const PATTERNS: [&[u8]; 8] = [
+    b"%41",
+    b"%e9",
+    b"%4",
+    b"%",
+    b"%%41",
+    b" ",
+    "\u{e9}".as_bytes(),
+    b"\xe2\x82",
+];
+
+fn check(src: &[u8]) {
+    for table in [PATH, IPATH, QUERY] {
+        let expected = table.validate_detailed(src);
+        for (name, f, f_detailed) in VALIDATE_FNS {
+            unsafe {
+                assert_eq!(f(table, src), expected.is_ok(), "{name} failed on {src:?}");
+                assert_eq!(f_detailed(table, src), expected, "{name} failed on {src:?}");
+            }
+        }
+
+        let expected = naive::decode_checked(table, src);
+        let res = unsafe { avx512::decode_checked(table, src) };
+        assert_eq!(res, expected, "avx512 decode_checked failed on {src:?}");
+        assert_eq!(decode_checked(table, src), expected);
+
+        let res: Result<Vec<_>, _> = query::split_query_checked(table, src).collect();
+        let expected = table
+            .or(query::DELIMITERS)
+            .validate_detailed(src)
+            .map(|()| query::split_query(src).collect::<Vec<_>>());
+        assert_eq!(res, expected, "split_query_checked failed on {src:?}");
+
+        let expected = naive::normalize(table, src);
+        let res = unsafe { avx512::normalize(table, src) };
+        assert_eq!(res, expected, "avx512 normalize failed on {src:?}");
+    }
+
+    let mut expected = Vec::new();
+    for chunk in Encode::new(PATH, src) {
+        expected.extend_from_slice(chunk.as_bytes());
+    }
+    for (name, f) in ENCODE_FNS {
+        let mut dst = b"prefix".to_vec();
+        unsafe { f(PATH, src, &mut dst) };
+        assert_eq!(dst[6..], expected, "{name} encode failed on {src:?}");
+    }
+
+    let mut buf = src.to_vec();
+    let len = naive::decode_in_place(&mut buf);
+    let expected = &buf[..len];
+    for (name, f) in DECODE_IN_PLACE_FNS {
+        let mut buf = src.to_vec();
+        let len = unsafe { f(&mut buf) };
+        assert_eq!(buf[..len], *expected, "{name} in place failed on {src:?}");
+    }
+
+    // the rest requires properly percent-encoded input
+    if !PATH.or(IPATH).or(QUERY).validate(src) {
+        return;
+    }
+    assert_eq!(Decode::new(src).to_bytes(), expected);
+    for (name, f) in DECODE_FNS {
+        let mut dst = Vec::with_capacity(src.len());
+        unsafe {
+            let len = f(src, dst.as_mut_ptr());
+            dst.set_len(len);
+        }
+        assert_eq!(dst, expected, "{name} decode failed on {src:?}");
+    }
+    let res = Decode::new(src).to_str();
+    let expected = std::str::from_utf8(expected);
+    assert_eq!(
+        res.as_deref().ok(),
+        expected.ok(),
+        "to_str failed on {src:?}"
+    );
+}
+
+// Every length up to 200 is covered, so that the tails of the 16-, 32- and
+// 64-byte blocks are hit with a triplet, a `%` or a UTF-8 sequence straddling
+// each block boundary and the end of the string.
+#[test]
+fn test_block_boundaries() {
+    const FILLER: &[u8] = b"az09-._~!$&'()*+,;=:@/?";
+
+    let mut seed = 0x9e3779b97f4a7c15u64;
+    for len in 0..=200 {
+        let filler: Vec<u8> = (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                FILLER[(seed % FILLER.len() as u64) as usize]
+            })
+            .collect();
+        check(&filler);
+
+        let near_boundary = |i: usize| i < 3 || i + 3 >= len || matches!(i % 16, 13..=15 | 0);
+        for i in (0..len).filter(|&i| near_boundary(i)) {
+            for pattern in PATTERNS {
+                let mut src = filler.clone();
+                let end = len.min(i + pattern.len());
+                src[i..end].copy_from_slice(&pattern[..end - i]);
+                check(&src);
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use pct_enc::{
+    dispatch::Backend,
+    naive::table_bitset::*,
+    query::{self, Spans},
+};
+
+/// Splits the query string, returning the pairs along with the index of
+/// the delimiter ending each of them, or the length for the last one.
+fn split_naive(src: &[u8]) -> Vec<(Spans, usize)> {
+    let mut pairs = Vec::new();
+    let mut start = 0;
+    for (i, &x) in src.iter().enumerate().chain([(src.len(), &b'&')]) {
+        if x != b'&' && x != b';' {
+            continue;
+        }
+        if start < i {
+            let pair = &src[start..i];
+            let spans = match pair.iter().position(|&x| x == b'=') {
+                Some(eq) => (start..start + eq, start + eq + 1..i),
+                None => (start..i, i..i),
+            };
+            pairs.push((spans, i));
+        }
+        start = i + 1;
+    }
+    pairs
+}
+
+fn check(table: Table, src: &[u8]) {
+    let pairs = split_naive(src);
+    let expected: Vec<_> = pairs.iter().map(|(spans, _)| Ok(spans.clone())).collect();
+    let expected_checked: Vec<_> = match table.or(query::DELIMITERS).validate_detailed(src) {
+        Ok(()) => expected.clone(),
+        Err(e) => pairs
+            .iter()
+            .filter(|&&(_, end)| end < e.offset())
+            .map(|(spans, _)| Ok(spans.clone()))
+            .chain([Err(e)])
+            .collect(),
+    };
+
+    for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+        let res: Vec<_> = query::split_query(src)
+            .with_backend(backend)
+            .map(Ok)
+            .collect();
+        assert_eq!(res, expected, "{backend} failed on {src:?}");
+
+        let res: Vec<_> = query::split_query_checked(table, src)
+            .with_backend(backend)
+            .collect();
+        assert_eq!(
+            res, expected_checked,
+            "{backend} (checked) failed on {src:?}"
+        );
+    }
+}
+
+#[test]
+fn test_split_query() {
+    let src = b"a=1&b=2;c&&=d&e==f&;g=";
+    let pairs: Vec<_> = query::split_query(src).collect();
+    assert_eq!(
+        pairs,
+        [
+            (0..1, 2..3),
+            (4..5, 6..7),
+            (8..9, 9..9),
+            (11..11, 12..13),
+            (14..15, 16..18),
+            (20..21, 22..22),
+        ]
+    );
+
+    let res: Vec<_> = query::split_query_checked(QUERY, b"a=1&b=%2g&c").collect();
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[0], Ok((0..1, 2..3)));
+    assert_eq!(res[1].clone().unwrap_err().offset(), 8);
+
+    // a delimiter following a `%` is not one
+    let res: Vec<_> = query::split_query_checked(QUERY, b"a=%&b").collect();
+    assert_eq!(res[0].clone().unwrap_err().offset(), 3);
+}
+
+#[test]
+fn test_split_query_lengths() {
+    const ALPHABET: &[&[u8]] = &[
+        b"a",
+        b"b",
+        b"=",
+        b"&",
+        b";",
+        b"%4F",
+        b"%",
+        b" ",
+        "é".as_bytes(),
+        b"\xc3",
+    ];
+
+    let mut seed = 0x2545f4914f6cdd1du64;
+    for len in 0..=200 {
+        for _ in 0..4 {
+            let mut src = Vec::new();
+            while src.len() < len {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                // mostly valid bytes, so that errors appear late
+                let k = (seed % 64) as usize;
+                src.extend_from_slice(ALPHABET[if k < 56 { k % 6 } else { k % 10 }]);
+            }
+            for table in [QUERY, IQUERY] {
+                check(table, &src);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_split_query_checked() {
+    let good = b"key=value&k%C3%A9y=%E2%82%AC;flag&x=/?:@!$'()*+,&";
+    let src = good.repeat(4);
+
+    for table in [QUERY, IQUERY, PCHAR, UNRESERVED] {
+        check(table, &src);
+    }
+
+    // single insertions at every position
+    for insert in [
+        &b" "[..],
+        b"%",
+        b"%4",
+        b"%&",
+        b"\xc3",
+        "é".as_bytes(),
+        b"\x80",
+    ] {
+        for i in 0..=src.len() {
+            let mut s = src.clone();
+            s.splice(i..i, insert.iter().copied());
+            for table in [QUERY, IQUERY] {
+                check(table, &s);
+            }
+        }
+    }
+
+    // errors past the first batch of blocks scanned by a kernel
+    let src = good.repeat(24);
+    for insert in [&b" "[..], b"%&", b"\xc3"] {
+        for i in (0..=src.len()).step_by(7) {
+            let mut s = src.clone();
+            s.splice(i..i, insert.iter().copied());
+            check(QUERY, &s);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use pct_enc::{
+    ValidateErrorKind,
+    naive::{self, table_bitset::*},
+    stream::*,
+};
+use std::io::{self, Read, Write};
+
+/// A reader that returns at most `n` bytes at a time.
+struct Trickle<'a>(&'a [u8], usize);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.len().min(self.1).min(buf.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+fn decode_chunks<'a>(
+    table: Table,
+    chunks: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<Vec<u8>, pct_enc::ValidateError> {
+    let mut validator = StreamValidator::new(table);
+    let mut decoder = StreamDecoder::new(table);
+    let mut dst = Vec::new();
+    for chunk in chunks {
+        let res = validator.update(chunk);
+        assert_eq!(res, decoder.decode(chunk, &mut dst).map(|_| ()));
+        if let Err(e) = res {
+            // errors are sticky
+            assert_eq!(validator.update(b"a"), Err(e));
+            assert_eq!(validator.finish(), Err(e));
+            return Err(e);
+        }
+    }
+    assert_eq!(validator.finish(), decoder.finish());
+    validator.finish().map(|_| dst)
+}
+
+fn check(table: Table, src: &[u8]) {
+    let expected = naive::decode_checked(table, src).map(|s| s.into_owned());
+
+    for size in 1..=5 {
+        let res = decode_chunks(table, src.chunks(size));
+        assert_eq!(res, expected, "chunks of {size} failed on {src:?}");
+    }
+    for i in 0..=src.len() {
+        for j in i..=src.len().min(i + 4) {
+            let res = decode_chunks(table, [&src[..i], &src[i..j], &src[j..]]);
+            assert_eq!(res, expected, "split at {i} and {j} failed on {src:?}");
+        }
+    }
+}
+
+#[test]
+fn test_stream() {
+    let cases: [&[u8]; 12] = [
+        b"",
+        b"abc",
+        b"%41%42%43",
+        b"%E2%82%ac/",
+        b"%%41",
+        b"%4%41",
+        b"%4",
+        b"%",
+        b"a%4g",
+        "/Stra\u{df}e/\u{65e5}\u{672c}/\u{1f600}".as_bytes(),
+        b"\xe2\x82",
+        b"\xe2\x82%41\xf0\x9f\x98",
+    ];
+
+    for src in cases {
+        for table in [PATH, IPATH, IQUERY] {
+            check(table, src);
+        }
+    }
+
+    let long = "a%20b/\u{e9}%C3%A9\u{1f600}".repeat(20);
+    check(IPATH, long.as_bytes());
+    check(PATH, long.as_bytes());
+}
+
+#[test]
+fn test_stream_errors() {
+    let mut validator = StreamValidator::new(PATH);
+    validator.update(b"abc%4").unwrap();
+    assert_eq!(validator.valid_up_to(), 3);
+    let e = validator.finish().unwrap_err();
+    assert_eq!(
+        (e.offset(), e.kind()),
+        (3, ValidateErrorKind::TruncatedTriplet)
+    );
+
+    validator.update(b"1%").unwrap();
+    assert_eq!(validator.valid_up_to(), 6);
+    let e = validator.update(b"z").unwrap_err();
+    assert_eq!((e.offset(), e.kind()), (7, ValidateErrorKind::NonHexDigit));
+
+    let mut validator = StreamValidator::new(IPATH);
+    validator.update(b"a\xe2").unwrap();
+    validator.update(b"\x82").unwrap();
+    let e = validator.finish().unwrap_err();
+    assert_eq!((e.offset(), e.kind()), (1, ValidateErrorKind::InvalidUtf8));
+    let e = validator.update(b"a").unwrap_err();
+    assert_eq!((e.offset(), e.kind()), (1, ValidateErrorKind::InvalidUtf8));
+}
+
+#[test]
+fn test_stream_io() -> io::Result<()> {
+    let src = "%2Fa%20b/\u{e9}%C3%A9".repeat(1000);
+    let src = src.as_bytes();
+    let expected = naive::decode_checked(IPATH, src).unwrap();
+
+    for n in [1, 7, 4096, usize::MAX] {
+        let mut validator = StreamValidator::new(IPATH);
+        io::copy(&mut Trickle(src, n), &mut validator)?;
+        validator.finish().unwrap();
+
+        let mut dst = Vec::new();
+        DecodeReader::new(IPATH, Trickle(src, n)).read_to_end(&mut dst)?;
+        assert_eq!(dst, *expected);
+
+        let mut writer = DecodeWriter::new(IPATH, Vec::new());
+        for chunk in src.chunks(n.min(src.len())) {
+            writer.write_all(chunk)?;
+        }
+        assert_eq!(writer.finish()?, *expected);
+    }
+
+    let mut dst = Vec::new();
+    let err = DecodeReader::new(PATH, &b"abc%4"[..])
+        .read_to_end(&mut dst)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut writer = DecodeWriter::new(PATH, Vec::new());
+    writer.write_all(b"abc%4")?;
+    let err = writer.finish().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let err = io::copy(&mut &b"a b"[..], &mut StreamValidator::new(PATH)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    Ok(())
+}
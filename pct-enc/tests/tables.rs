@@ -0,0 +1,228 @@
+use pct_enc::naive::{Table as _, table_bitset, table_bool_array};
+
+macro_rules! assert_tables_eq {
+    ($($name:ident),+ $(,)?) => {
+        $(
+            let (a, b) = (table_bitset::$name, table_bool_array::$name);
+            assert_eq!(a.allows_pct_encoded(), b.allows_pct_encoded(), stringify!($name));
+            for x in 0..=255 {
+                assert_eq!(a.validate(&[x]), b.validate(&[x]), "{} at {x}", stringify!($name));
+            }
+            assert_eq!(a.allows_ucschar(), b.allows_ucschar(), stringify!($name));
+            assert_eq!(a.allows_iprivate(), b.allows_iprivate(), stringify!($name));
+            assert_eq!(b.to_bitset(), a, stringify!($name));
+            assert_eq!(table_bool_array::Table::from_bitset(a).to_bitset(), a, stringify!($name));
+            check_agree(a, b);
+        )+
+    };
+}
+
+/// Checks that the trait methods and the functions taking any table
+/// agree on the two representations.
+fn check_agree(a: table_bitset::Table, b: table_bool_array::Table) {
+    use pct_enc::{
+        avx512,
+        dispatch::Backend,
+        naive::{self, EStr, Encode},
+        query::split_query_checked,
+        stream::StreamValidator,
+    };
+
+    let inputs: [&[u8]; 7] = [
+        b"",
+        b"azAZ09-._~!$&'()*+,;=:@/?",
+        b"%41%c3%A9",
+        b"%4g",
+        b"a b",
+        "\u{e9}\u{e000}\u{fffe}".as_bytes(),
+        &[0, 0x7f, 0x80, 0xff],
+    ];
+    for src in inputs {
+        let src = src.repeat(5);
+        let expected = a.validate_detailed(&src);
+        assert_eq!(b.validate_detailed(&src), expected);
+        assert_eq!(b.validate(&src), expected.is_ok());
+        for backend in Backend::ALL.into_iter().filter(|b| b.is_supported()) {
+            assert_eq!(backend.validate_detailed(b, &src), expected);
+        }
+        unsafe {
+            assert_eq!(avx512::validate_iri_detailed(b, &src), expected);
+        }
+        assert!(Encode::new(a, &src).eq(b.encode(&src)));
+        assert_eq!(naive::normalize(b, &src), naive::normalize(a, &src));
+        assert_eq!(EStr::new(b, &src), EStr::new(a, &src));
+        let mut validator = StreamValidator::new(b);
+        assert_eq!(validator.update(&src).and(validator.finish()), expected);
+        assert!(split_query_checked(b, &src).eq(split_query_checked(a, &src)));
+    }
+    for x in 0..=255 {
+        assert_eq!(a.allows(x), b.allows(x));
+    }
+}
+
+#[test]
+fn test_tables_eq() {
+    assert_tables_eq!(
+        ALPHA,
+        DIGIT,
+        HEXDIG,
+        SCHEME,
+        USERINFO,
+        IPV_FUTURE,
+        REG_NAME,
+        PATH,
+        PCHAR,
+        SEGMENT_NZ_NC,
+        QUERY,
+        FRAGMENT,
+        UNRESERVED,
+        RESERVED,
+        GEN_DELIMS,
+        SUB_DELIMS,
+        ZONE_ID,
+        FORM_URLENCODED,
+        UCSCHAR,
+        IPRIVATE,
+        IUNRESERVED,
+        IPCHAR,
+        IPATH,
+        IQUERY,
+        IFRAGMENT,
+    );
+}
+
+#[test]
+fn test_tables() {
+    use table_bitset::*;
+
+    assert!(SCHEME.validate(b"svn+ssh"));
+    assert!(!SCHEME.validate(b"svn_ssh"));
+    assert!(USERINFO.validate(b"user:pass%20word"));
+    assert!(!USERINFO.validate(b"user@host"));
+    assert!(REG_NAME.validate(b"example.com"));
+    assert!(!REG_NAME.validate(b"example.com:80"));
+    assert!(SEGMENT_NZ_NC.validate(b"a@b"));
+    assert!(!SEGMENT_NZ_NC.validate(b"a:b"));
+    assert!(QUERY.validate(b"/a?b=c&d=%20"));
+    assert!(!QUERY.validate(b"a#b"));
+    assert!(RESERVED.validate(b":/?#[]@!$&'()*+,;="));
+    assert!(!RESERVED.validate(b"a"));
+    assert!(IPV_FUTURE.validate(b"1.fe80::a+en1"));
+    assert!(!IPV_FUTURE.validate(b"a%20"));
+    assert!(ZONE_ID.validate(b"en1%25"));
+    assert!(!ZONE_ID.validate(b"en1/"));
+}
+
+#[test]
+fn test_tables_non_ascii() {
+    // ucschar and iprivate as listed in RFC 3987
+    fn is_ucschar(c: u32) -> bool {
+        matches!(c, 0xa0..=0xd7ff | 0xf900..=0xfdcf | 0xfdf0..=0xffef | 0xe1000..=0xefffd)
+            || ((1..=0xd).contains(&(c >> 16)) && c & 0xffff <= 0xfffd)
+    }
+    fn is_iprivate(c: u32) -> bool {
+        matches!(c, 0xe000..=0xf8ff | 0xf0000..=0xffffd | 0x100000..=0x10fffd)
+    }
+
+    let tables = [
+        (table_bitset::UCSCHAR, table_bool_array::UCSCHAR),
+        (table_bitset::IPRIVATE, table_bool_array::IPRIVATE),
+        (table_bitset::IQUERY, table_bool_array::IQUERY),
+        (table_bitset::PATH, table_bool_array::PATH),
+    ];
+
+    let mut buf = [0; 4];
+    for c in ('\u{80}'..=char::MAX).map(|c| c as u32) {
+        let s = char::from_u32(c).unwrap().encode_utf8(&mut buf).as_bytes();
+        for (a, b) in tables {
+            let expected =
+                (a.allows_ucschar() && is_ucschar(c)) || (a.allows_iprivate() && is_iprivate(c));
+            assert_eq!(a.validate(s), expected, "U+{c:04X}");
+            assert_eq!(b.validate(s), expected, "U+{c:04X}");
+        }
+    }
+
+    use table_bitset::*;
+
+    assert!(IPATH.validate("/wiki/Straße/日本語".as_bytes()));
+    assert!(!PATH.validate("/wiki/Straße".as_bytes()));
+    assert!(IQUERY.validate("q=\u{f0000}".as_bytes()));
+    assert!(!IPATH.validate("\u{f0000}".as_bytes()));
+    assert!(!IPATH.validate(b"\xc3"));
+}
+
+#[test]
+fn test_table_algebra() {
+    use std::collections::HashSet;
+    use table_bitset::*;
+
+    assert_eq!(ALPHA.and(HEXDIG), Table::new(b"ABCDEFabcdef"));
+    assert_eq!(
+        UNRESERVED.difference(ALPHA).difference(DIGIT),
+        Table::new(b"-._~")
+    );
+    assert_eq!(PCHAR.and(QUERY), PCHAR);
+    assert_eq!(PCHAR.and(SUB_DELIMS), SUB_DELIMS);
+    assert_eq!(IPCHAR.and(PCHAR), PCHAR);
+    assert_eq!(IQUERY.difference(QUERY), IPRIVATE.or(UCSCHAR));
+    assert_ne!(QUERY, IQUERY);
+
+    // the complement leaves % and non-ASCII alone
+    let c = PCHAR.complement_ascii();
+    assert!(c.allows_pct_encoded() && !c.contains(b'%'));
+    assert_eq!(c.len(), 127 - PCHAR.len());
+    assert_eq!(c.or(PCHAR).len(), 127);
+    assert!(c.and(PCHAR).is_empty());
+    assert_eq!(c.complement_ascii(), PCHAR);
+    assert_eq!(IPATH.complement_ascii().complement_ascii(), IPATH);
+
+    for table in [ALPHA, PCHAR, QUERY, RESERVED, IPATH, Table::new(&[0, 127])] {
+        let bytes: Vec<u8> = table.iter().collect();
+        assert_eq!(bytes.len(), table.len());
+        assert_eq!(table.iter().len(), table.len());
+        assert!(bytes.is_sorted());
+        for x in 0..=255 {
+            assert_eq!(table.contains(x), bytes.contains(&x));
+            assert_eq!(table.contains(x), table.validate(&[x]) && x != b'%');
+        }
+        if !table.allows_pct_encoded() && !table.allows_non_ascii() {
+            assert_eq!(Table::new(&bytes), table);
+        }
+    }
+    assert_eq!(DIGIT.into_iter().collect::<Vec<_>>(), b"0123456789");
+    assert!(Table::new(b"").is_empty());
+    assert!(!Table::new(b"").or_pct_encoded().contains(b'%'));
+
+    let set: HashSet<Table> = [PATH, PCHAR.or(Table::new(b"/")), FRAGMENT, QUERY].into();
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn test_table_display() {
+    use table_bitset::*;
+
+    let cases = [
+        (DIGIT, "%x30-39"),
+        (ALPHA, "%x41-5A / %x61-7A"),
+        (HEXDIG, "%x30-39 / %x41-46 / %x61-66"),
+        (
+            UNRESERVED,
+            r#""-" / "." / %x30-39 / %x41-5A / "_" / %x61-7A / "~""#,
+        ),
+        (
+            ZONE_ID.and(Table::new(b"-.~")).or_pct_encoded(),
+            r#""-" / "." / "~" / pct-encoded"#,
+        ),
+        (GEN_DELIMS, r##""#" / "/" / ":" / "?" / "@" / "[" / "]""##),
+        (
+            Table::new(b"\"a\0\x7f "),
+            r#"%x00 / %x20 / %x22 / %x61 / %x7F"#,
+        ),
+        (Table::new(b""), r#""""#),
+        (IQUERY.difference(QUERY), "ucschar / iprivate"),
+    ];
+    for (table, expected) in cases {
+        assert_eq!(table.to_string(), expected);
+    }
+    assert_eq!(format!("{DIGIT:?}"), "Table(%x30-39)");
+}
@@ -1,20 +1,39 @@
-use pct_enc::*;
+use pct_enc::{naive::table_bitset::*, *};
+
+type DetailedFn = unsafe fn(Table, &[u8]) -> Result<(), ValidateError>;
+
+const FNS: [unsafe fn(Table, &[u8]) -> bool; 12] = [
+    ssse3::validate_3load,
+    sse41::validate_3load,
+    sse41::validate_alignr,
+    sse41::validate_alignl,
+    avx2::validate_3load,
+    avx2::validate_alignr,
+    avx2::validate_alignl,
+    avx512::validate_3load,
+    avx512::validate_3load_gf2p8affine,
+    avx512::validate_3load_perm,
+    avx512::validate_alignr,
+    avx512::validate_alignl,
+];
+
+const DETAILED_FNS: [DetailedFn; 12] = [
+    ssse3::validate_3load_detailed,
+    sse41::validate_3load_detailed,
+    sse41::validate_alignr_detailed,
+    sse41::validate_alignl_detailed,
+    avx2::validate_3load_detailed,
+    avx2::validate_alignr_detailed,
+    avx2::validate_alignl_detailed,
+    avx512::validate_3load_detailed,
+    avx512::validate_3load_gf2p8affine_detailed,
+    avx512::validate_3load_perm_detailed,
+    avx512::validate_alignr_detailed,
+    avx512::validate_alignl_detailed,
+];
 
 #[test]
 fn test_validate() {
-    let fns = [
-        ssse3::validate_3load,
-        sse41::validate_3load,
-        sse41::validate_alignr,
-        sse41::validate_alignl,
-        avx2::validate_3load,
-        avx2::validate_alignr,
-        avx2::validate_alignl,
-        avx512::validate_3load,
-        avx512::validate_3load_gf2p8affine,
-        avx512::validate_3load_perm,
-    ];
-
     let good = b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA";
     let not_hexdig =
         b"%3C%8,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA";
@@ -25,14 +44,390 @@ fn test_validate() {
     let incomplete_1 = b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93%1D%CD%25%13%3F%9";
     let incomplete_2 = b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93%1Dd%CD%25%13%3%99";
 
-    for (i, f) in fns.into_iter().enumerate() {
+    for (i, f) in FNS.into_iter().enumerate() {
         unsafe {
-            assert!(f(good), "good failed on {i}");
-            assert!(!f(not_hexdig), "disallowed failed on {i}");
-            assert!(!f(null), "null failed on {i}");
-            assert!(!f(non_ascii), "non-ASCII failed on {i}");
-            assert!(!f(incomplete_1), "incomplete_1 failed on {i}");
-            assert!(!f(incomplete_2), "incomplete_2 failed on {i}");
+            assert!(f(PATH, good), "good failed on {i}");
+            assert!(!f(PATH, not_hexdig), "disallowed failed on {i}");
+            assert!(!f(PATH, null), "null failed on {i}");
+            assert!(!f(PATH, non_ascii), "non-ASCII failed on {i}");
+            assert!(!f(PATH, incomplete_1), "incomplete_1 failed on {i}");
+            assert!(!f(PATH, incomplete_2), "incomplete_2 failed on {i}");
+        }
+    }
+}
+
+#[test]
+fn test_validate_tables() {
+    let tables = [
+        ("PATH", PATH),
+        ("PCHAR", PCHAR),
+        ("UNRESERVED", UNRESERVED),
+        ("SUB_DELIMS", SUB_DELIMS),
+        // hex digits after % are not allowed unencoded
+        ("DIGIT_PCT", DIGIT.or_pct_encoded()),
+    ];
+
+    let inputs: [Vec<u8>; 12] = [
+        b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA"
+            .repeat(3),
+        b"Az09-._~".repeat(40),
+        b"Az09-._~/".repeat(40),
+        b"!$&'()*+,;=".repeat(30),
+        b"0123%AB456789%cd".repeat(20),
+        [&b"0123456789".repeat(20)[..], b"%A"].concat(),
+        [&b"0123456789".repeat(20)[..], b"%AB"].concat(),
+        [&b"0123456789".repeat(20)[..], b"A"].concat(),
+        [&b"%41".repeat(70)[..], b"%"].concat(),
+        [&b"0123456789".repeat(20)[..], b"%\xff0"].concat(),
+        [&b"0123456789".repeat(20)[..], b"\xff%4"].concat(),
+        [&b"%41".repeat(70)[..], b"%%41"].concat(),
+    ];
+
+    for (name, table) in tables {
+        for src in &inputs {
+            for len in 0..=src.len() {
+                let src = &src[..len];
+                let expected = table.validate(src);
+                for (i, f) in FNS.into_iter().enumerate() {
+                    let res = unsafe { f(table, src) };
+                    assert_eq!(res, expected, "{name} failed on {i} with len {len}");
+                }
+
+                let expected = table.validate_detailed(src);
+                assert_eq!(expected.is_ok(), table.validate(src));
+                for (i, f) in DETAILED_FNS.into_iter().enumerate() {
+                    let res = unsafe { f(table, src) };
+                    assert_eq!(res, expected, "{name} failed on {i} with len {len}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dispatch() {
+    let src = b"%3C%88,Kh%9C%3E%90%3F@%BB%B4%E8%96%18%9F%3C%5C%93@%1D%CD%25%13%3F%99%1CP%FA%88%EA"
+        .repeat(3);
+
+    assert!(validate(PATH, &src));
+    assert!(!validate(UNRESERVED, &src));
+
+    for backend in dispatch::Backend::ALL {
+        if !backend.is_supported() {
+            continue;
+        }
+        assert!(backend.validate(PATH, &src), "{backend} failed");
+        assert!(!backend.validate(UNRESERVED, &src), "{backend} failed");
+        assert_eq!(
+            backend.validate_detailed(PATH, &src),
+            Ok(()),
+            "{backend} failed"
+        );
+        assert_eq!(
+            backend.validate_detailed(UNRESERVED, &src),
+            validate_detailed(UNRESERVED, &src),
+            "{backend} failed"
+        );
+        assert_eq!(dispatch::Backend::from_name(backend.name()), Some(backend));
+    }
+}
+
+#[test]
+fn test_validate_detailed() {
+    use ValidateErrorKind::*;
+
+    type Case<'a> = (&'a [u8], Option<(usize, ValidateErrorKind)>);
+
+    let cases: [Case; 9] = [
+        (b"%3C%88,Kh%9C", None),
+        (b"%3C%8,Kh%9C", Some((5, NonHexDigit))),
+        (b"%3C%88\0,Kh%9C", Some((6, DisallowedByte))),
+        (b"%3C%88\xf0,Kh%9C", Some((6, NonAsciiByte))),
+        (b"%3C%88,Kh%9C%3", Some((12, TruncatedTriplet))),
+        (b"%3C%88,Kh%9C%", Some((12, TruncatedTriplet))),
+        (b"%3C%88,Kh%9C%3%", Some((14, NonHexDigit))),
+        (b"%3C%\xf0", Some((4, NonHexDigit))),
+        (b"%G", Some((1, NonHexDigit))),
+    ];
+
+    // long enough to enter the SIMD loops
+    let prefix = b"Kh".repeat(80);
+
+    for (src, expected) in cases {
+        let src = [&prefix[..], src].concat();
+        let expected = expected.map(|(offset, kind)| (offset + prefix.len(), kind));
+
+        let res = PATH.validate_detailed(&src);
+        assert_eq!(res.err().map(|e| (e.offset(), e.kind())), expected);
+        assert_eq!(naive::table_bool_array::PATH.validate_detailed(&src), res);
+        assert_eq!(validate_detailed(PATH, &src), res);
+
+        for (i, f) in DETAILED_FNS.into_iter().enumerate() {
+            assert_eq!(unsafe { f(PATH, &src) }, res, "failed on {i}");
+        }
+    }
+
+    let err = UNRESERVED.validate_detailed(b"abc%41").unwrap_err();
+    assert_eq!((err.offset(), err.kind()), (3, DisallowedByte));
+}
+
+#[test]
+fn test_validate_iri() {
+    use ValidateErrorKind::*;
+
+    let tables = [
+        ("IPATH", IPATH),
+        ("IQUERY", IQUERY),
+        ("IUNRESERVED", IUNRESERVED),
+        ("IPRIVATE", IPRIVATE.or(PATH)),
+        ("PATH", PATH),
+    ];
+
+    // code points around the boundaries of ucschar and iprivate
+    let boundaries = [
+        0x7f, 0x80, 0x9f, 0xa0, 0x7ff, 0x800, 0xd7ff, 0xe000, 0xf8ff, 0xf900, 0xfdcf, 0xfdd0,
+        0xfdef, 0xfdf0, 0xffef, 0xfff0, 0xfffd, 0xffff, 0x10000, 0x1fffd, 0x1fffe, 0x1ffff,
+        0x20000, 0xdfffd, 0xdfffe, 0xe0000, 0xe0fff, 0xe1000, 0xefffd, 0xefffe, 0xf0000, 0xffffd,
+        0xffffe, 0x100000, 0x10fffd, 0x10ffff,
+    ];
+    let mut boundary_chars = String::new();
+    for (i, c) in boundaries.into_iter().enumerate() {
+        boundary_chars.push_str(&"ab/"[..i % 4]);
+        boundary_chars.push(char::from_u32(c).unwrap());
+    }
+
+    let inputs: [Vec<u8>; 7] = [
+        "/wiki/Straße/日本語/😀?q=%E2%82%AC&x=Ωμέγα"
+            .repeat(8)
+            .into_bytes(),
+        "%41é%42".repeat(50).into_bytes(),
+        ("a".repeat(63) + &"😀".repeat(30)).into_bytes(),
+        boundary_chars.repeat(3).into_bytes(),
+        [
+            &"é".repeat(40).into_bytes()[..],
+            b"\xc0\x80",
+            "é".as_bytes(),
+        ]
+        .concat(),
+        [
+            &b"a".repeat(62)[..],
+            b"\xed\xa0\x80\xf4\x90\x80\x80\xe0\x80\xaf",
+        ]
+        .concat(),
+        [
+            &"日本".repeat(20).into_bytes()[..],
+            b"\xe2\x82a\xf0\x9f\x98a\x80\xff%4\xe2",
+        ]
+        .concat(),
+    ];
+
+    for (name, table) in tables {
+        let table_bool_array = match name {
+            "IPATH" => naive::table_bool_array::IPATH,
+            "IQUERY" => naive::table_bool_array::IQUERY,
+            "IUNRESERVED" => naive::table_bool_array::IUNRESERVED,
+            "IPRIVATE" => naive::table_bool_array::IPRIVATE.or(naive::table_bool_array::PATH),
+            _ => naive::table_bool_array::PATH,
+        };
+        for src in &inputs {
+            for len in 0..=src.len() {
+                let src = &src[..len];
+                let expected = table.validate(src);
+                assert_eq!(table_bool_array.validate(src), expected);
+                for (i, f) in FNS.into_iter().enumerate() {
+                    let res = unsafe { f(table, src) };
+                    assert_eq!(res, expected, "{name} failed on {i} with len {len}");
+                }
+                assert_eq!(unsafe { avx512::validate_iri(table, src) }, expected);
+
+                let expected = table.validate_detailed(src);
+                assert_eq!(expected.is_ok(), table.validate(src));
+                assert_eq!(table_bool_array.validate_detailed(src), expected);
+                for (i, f) in DETAILED_FNS.into_iter().enumerate() {
+                    let res = unsafe { f(table, src) };
+                    assert_eq!(res, expected, "{name} failed on {i} with len {len}");
+                }
+                let res = unsafe { avx512::validate_iri_detailed(table, src) };
+                assert_eq!(res, expected, "{name} failed on iri with len {len}");
+                assert_eq!(validate_detailed(table, src), expected);
+            }
+        }
+    }
+
+    // a single code point or ill-formed sequence at every offset within a block,
+    // so that it is the only possible failure in the block
+    let mut singles: Vec<Vec<u8>> = boundaries
+        .map(|c| char::from_u32(c).unwrap().to_string().into_bytes())
+        .to_vec();
+    singles.extend(
+        [
+            &b"\x80"[..],
+            b"\xff",
+            b"\xc3",
+            b"\xc0\x80",
+            b"\xe2\x82",
+            b"\xe0\x80\xaf",
+            b"\xed\xa0\x80",
+            b"\xf0\x9f\x98",
+            b"\xf4\x90\x80\x80",
+            b"\xf5\x80\x80\x80",
+            b"%",
+            b"%4",
+            b"%C3%A9",
+        ]
+        .map(|s| s.to_vec()),
+    );
+
+    for (name, table) in tables {
+        for single in &singles {
+            for pad in 0..64 {
+                let src = [
+                    "a".repeat(pad).as_bytes(),
+                    "é".repeat(40).as_bytes(),
+                    single,
+                    "/a".repeat(40).as_bytes(),
+                    "日".repeat(10).as_bytes(),
+                ]
+                .concat();
+                let expected = table.validate_detailed(&src);
+                for (i, f) in DETAILED_FNS.into_iter().enumerate() {
+                    let res = unsafe { f(table, &src) };
+                    assert_eq!(
+                        res, expected,
+                        "{name} failed on {i} with {single:x?}, pad {pad}"
+                    );
+                }
+                let res = unsafe { avx512::validate_iri_detailed(table, &src) };
+                assert_eq!(
+                    res, expected,
+                    "{name} failed on iri with {single:x?}, pad {pad}"
+                );
+            }
+        }
+    }
+
+    type Case<'a> = (&'a [u8], Option<(usize, ValidateErrorKind)>);
+
+    let cases: [Case; 7] = [
+        ("Straße/日本語".as_bytes(), None),
+        (b"Stra\xc3", Some((4, InvalidUtf8))),
+        (b"Stra\xdf\xc3", Some((4, InvalidUtf8))),
+        (b"Stra\xed\xa0\x80", Some((4, InvalidUtf8))),
+        (b"Stra\xc2\x80", Some((4, DisallowedCodePoint))),
+        ("Stra\u{e000}".as_bytes(), Some((4, DisallowedCodePoint))),
+        (b"%C3\xc3\xa9%", Some((5, TruncatedTriplet))),
+    ];
+
+    let prefix = "Kh日本".repeat(30);
+
+    for (src, expected) in cases {
+        let src = [prefix.as_bytes(), src].concat();
+        let expected = expected.map(|(offset, kind)| (offset + prefix.len(), kind));
+
+        let res = IPATH.validate_detailed(&src);
+        assert_eq!(res.err().map(|e| (e.offset(), e.kind())), expected);
+        assert_eq!(unsafe { avx512::validate_iri_detailed(IPATH, &src) }, res);
+    }
+
+    assert!(IQUERY.validate("?\u{e000}".as_bytes()));
+    assert!(!IFRAGMENT.validate("#\u{e000}".as_bytes()));
+}
+
+#[test]
+fn test_par_validate() {
+    let tables = [
+        ("PATH", PATH),
+        ("IPATH", IPATH),
+        // hex digits after % are not allowed unencoded
+        ("DIGIT_PCT", DIGIT.or_pct_encoded()),
+    ];
+    let inserts: [&[u8]; 8] = [
+        b"%41",
+        b"%4",
+        b"%",
+        b"%%41",
+        b"%\xc3",
+        b" ",
+        "\u{1f600}".as_bytes(),
+        b"\xe2\x82",
+    ];
+    let good = "12%41\u{e9}/0%e2%82%ac9".repeat(20).into_bytes();
+
+    for (name, table) in tables {
+        for threads in 1..=5 {
+            let res = par_validate(table, &good, threads);
+            assert_eq!(res, validate_detailed(table, &good));
+        }
+
+        // insertions around the chunk boundaries
+        for i in (0..good.len()).filter(|i| matches!(i % 64, 0..4 | 60..)) {
+            for insert in inserts {
+                let mut src = good.clone();
+                src.splice(i..i, insert.iter().copied());
+                let expected = validate_detailed(table, &src);
+                for threads in [2, 3, 5, 8] {
+                    let res = par_validate(table, &src, threads);
+                    assert_eq!(res, expected, "{name} failed with {threads} threads at {i}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_validate_many() {
+    let tables = [
+        ("PATH", PATH),
+        ("IPATH", IPATH),
+        // hex digits after % are not allowed unencoded
+        ("DIGIT_PCT", DIGIT.or_pct_encoded()),
+    ];
+    const ALPHABET: &[&[u8]] = &[
+        b"1",
+        b"a",
+        b"/",
+        b"%41",
+        b"%",
+        b"%4",
+        b" ",
+        b"\xc3\xa9",
+        b"\xc3",
+    ];
+
+    // strings of lengths 0..=70, mostly valid and ending in every way
+    let mut seed = 0x2545f4914f6cdd1du64;
+    let mut srcs = Vec::new();
+    for len in (0..=70).cycle().take(2000) {
+        let mut src = Vec::new();
+        while src.len() < len {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let k = (seed % 64) as usize;
+            src.extend_from_slice(ALPHABET[if k < 48 { k % 4 } else { k % 9 }]);
+        }
+        srcs.push(src);
+    }
+    let srcs: Vec<&[u8]> = srcs.iter().map(|src| &src[..]).collect();
+
+    for (name, table) in tables {
+        let expected: Vec<_> = srcs.iter().map(|src| table.validate(src)).collect();
+        assert!(expected.iter().any(|&ok| ok) && expected.iter().any(|&ok| !ok));
+
+        assert_eq!(validate_many(table, &srcs), expected, "{name} failed");
+        for backend in dispatch::Backend::ALL
+            .into_iter()
+            .filter(|b| b.is_supported())
+        {
+            let res = backend.validate_many(table, &srcs);
+            assert_eq!(res, expected, "{name} failed with {backend}");
+        }
+        assert_eq!(unsafe { avx512::validate_many(table, &srcs) }, expected);
+        // starting the packing at every string
+        for i in 0..70 {
+            let res = unsafe { avx512::validate_many(table, &srcs[i..]) };
+            assert_eq!(res, expected[i..], "{name} failed from {i}");
         }
     }
+    assert!(validate_many(PATH, &[]).is_empty());
 }